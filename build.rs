@@ -0,0 +1,23 @@
+//! Generates the attack tables (and the magic numbers indexing the slider ones)
+//! into `$OUT_DIR/attack_tables.rs`, which `src/attacks.rs` includes.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+#[path = "src/codegen.rs"]
+mod codegen;
+#[path = "src/rand.rs"]
+mod rand;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/codegen.rs");
+    println!("cargo:rerun-if-changed=src/rand.rs");
+
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let path = Path::new(&out_dir).join("attack_tables.rs");
+    let mut out = BufWriter::new(File::create(path).unwrap());
+
+    codegen::write_attack_tables(&mut out).unwrap();
+}
@@ -7,29 +7,9 @@ use crate::squares::*;
 
 const PIECE_TABLE: [char; 12] = ['♙', '♘', '♗', '♖', '♕', '♔', '♟', '♞', '♝', '♜', '♛', '♚'];
 
-pub fn print_bitboard(bitboard: u64) {
-    println!("\n  -*- {:#016X} -*-", bitboard);
-
-    println!("      ---------------");
-    for rank in 0..8 {
-        print!(" {}  |", 8 - rank);
-        for file in 0..8 {
-            let square = rank * 8 + file;
-
-            print!(
-                " {}",
-                if get_bit(bitboard, square) {
-                    '×'
-                } else {
-                    '·'
-                }
-            );
-        }
-        println!(" |");
-    }
-
-    println!("      ---------------");
-    println!("      a b c d e f g h\n");
+pub fn print_bitboard(bitboard: Bitboard) {
+    println!("\n  -*- {:#016X} -*-", bitboard.0);
+    println!("{}\n", bitboard);
 }
 
 pub fn print_board(board: &Board) {
@@ -37,22 +17,13 @@ pub fn print_board(board: &Board) {
     for rank in 0..8 {
         print!(" {}  |", 8 - rank);
         for file in 0..8 {
-            let square = rank * 8 + file;
-
-            let mut piece = -1i32;
-            for (i, p) in board.pieces.iter().enumerate() {
-                if get_bit(*p, square) {
-                    piece = i as i32;
-                    break;
-                }
-            }
+            let square = Square::from_rank_file(rank, file);
 
             print!(
                 " {}",
-                if piece == -1 {
-                    '·'
-                } else {
-                    PIECE_TABLE[piece as usize]
+                match board.piece_at(square) {
+                    Some(piece) => PIECE_TABLE[piece as usize],
+                    None => '·',
                 }
             );
         }
@@ -66,48 +37,33 @@ pub fn print_board(board: &Board) {
         "Side to move: {:?} \nCastling: {:04b}\nEn-passant: {}\n",
         board.side_to_move,
         board.castling_rights,
-        if board.en_passant_square == NO_SQUARE {
-            "ø"
-        } else {
-            CELL_NAMES[board.en_passant_square as usize]
+        match board.en_passant_square {
+            Some(square) => square.name(),
+            None => "ø",
         }
     );
 }
 
 pub fn print_attacked_squares(board: &Board, side: Side) {
-    println!("      ---------------");
-    for rank in 0..8 {
-        print!(" {}  |", 8 - rank);
-        for file in 0..8 {
-            let square = rank * 8 + file;
-
-            print!(
-                " {}",
-                if is_square_attacked(board, square, side) {
-                    '×'
-                } else {
-                    '·'
-                }
-            );
-        }
-        println!(" |");
-    }
+    let attacked: Bitboard = Square::all()
+        .filter(|square| is_square_attacked(board, *square, side))
+        .collect();
 
-    println!("      ---------------");
-    println!("      a b c d e f g h\n");
+    println!("{}\n", attacked);
 }
 
-pub fn print_move(mv: i32) {
+pub fn print_move(mv: Move) {
     println!(
-        "{}{} {} (capture: {}, en-passant: {}, castles: {}, double push: {}, promotion: {} ({}))",
-        CELL_NAMES[Move::decode_src_square(mv) as usize],
-        CELL_NAMES[Move::decode_dst_square(mv) as usize],
-        PIECE_TABLE[Move::decode_piece(mv) as usize],
-        Move::is_capture(mv),
-        Move::is_en_passant(mv),
-        Move::is_castling(mv),
-        Move::is_double_push(mv),
-        Move::is_promotion(mv),
-        PIECE_TABLE[Move::decode_promotion_piece(mv) as usize],
+        "{} {} (capture: {}, en-passant: {}, castles: {}, double push: {}, promotion: {})",
+        mv,
+        PIECE_TABLE[mv.piece() as usize],
+        mv.is_capture(),
+        mv.is_en_passant(),
+        mv.is_castling(),
+        mv.is_double_push(),
+        match mv.promotion_piece() {
+            Some(promotion_piece) => PIECE_TABLE[promotion_piece as usize],
+            None => '-',
+        },
     );
 }
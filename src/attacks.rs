@@ -1,747 +1,410 @@
 #![allow(dead_code)]
 
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
 
 use crate::bitboard::*;
 use crate::board::Side;
-use crate::codegen::get_square;
-
-const BISHOP_MAGIC_NUMBERS: [u64; 64] = [
-    22698322406146064,
-    1205099108012032,
-    9228087301064687616,
-    73187894424010752,
-    1130444285345906,
-    4612275373907992576,
-    2305988214307962881,
-    2306019103008638978,
-    288866237490987264,
-    72216138562421252,
-    1162214595146940544,
-    37163512463630369,
-    2315977277682221568,
-    9368050759537001484,
-    4611691533434038336,
-    38484651935748,
-    594475288664083602,
-    9052289030553760,
-    327074215130433544,
-    7298083213874892800,
-    147492889954385920,
-    72093336789393409,
-    10376383993509711872,
-    2341908098781939841,
-    9239486531352396033,
-    2312604190083450881,
-    1130332548499472,
-    4612816316921872896,
-    18159534086299648,
-    148636654801256960,
-    1162211278356744200,
-    70922811770880,
-    1157434484545553041,
-    290539367752177667,
-    17334400050057448448,
-    9259403035103723648,
-    4515153089667584,
-    186354585114313280,
-    581395369348858368,
-    720934383325946884,
-    1585550262873362440,
-    330174554376773637,
-    1171780878892830720,
-    2378079007612618752,
-    211141968036416,
-    299273329573952,
-    577025935659768325,
-    5910134490650908032,
-    564067182052352,
-    4857645661978624,
-    86695402290546696,
-    4828157972997996608,
-    144124122148962304,
-    4508857473761281,
-    2310771089116012544,
-    6918656061436134410,
-    288250176238258186,
-    5774531719424258058,
-    1152921783790252048,
-    2325469252946049,
-    432381023788073472,
-    3476780081401086209,
-    28237116734505473,
-    9223961384147108352,
-];
-
-const ROOK_MAGIC_NUMBERS: [u64; 64] = [
-    36029347315843096,
-    2395915482942144836,
-    4683761243343822848,
-    5800645116214378624,
-    144117387167277064,
-    72061992218788096,
-    4935947390663263488,
-    144120840269693010,
-    586734589561537152,
-    310889386656923784,
-    144678277649272896,
-    13835339598980451584,
-    141287378387968,
-    216876478145757312,
-    39406501303190056,
-    9386908999430996224,
-    2323866753710456865,
-    4508272553886784,
-    1442564753737138193,
-    4611829504963250176,
-    9223672203663441924,
-    2309361996245500416,
-    9238030728695972368,
-    9277804459638423812,
-    36029077265653760,
-    1152956698642894848,
-    1198476265293856,
-    2612378338414690576,
-    1227232002265580944,
-    4612249870324477956,
-    7116056435917058,
-    2305861159746216004,
-    450430333637099553,
-    1342107942052167744,
-    81099979820634120,
-    281509890101248,
-    9232519990786131968,
-    577024595912038404,
-    4909064335625552384,
-    12686640717271924801,
-    11529250781270147073,
-    4503875042164736,
-    864972740875059216,
-    2251868541583369,
-    4647719213627113600,
-    9251519543116071040,
-    11402004316553224,
-    6918093079257940004,
-    282171843543808,
-    11558204743909632,
-    589976018488426624,
-    153162004377436416,
-    11533727443936608384,
-    18295890666586880,
-    144258130091484160,
-    594485269764262400,
-    9223392120675533314,
-    1729418542945669638,
-    635517997811746,
-    4902449807076901963,
-    4756364173907068930,
-    302304468669302826,
-    565153280561156,
-    277027554306,
-];
-
-fn mask_pawn_attacks(square: i32, side: Side) -> u64 {
-    let mut attacks = 0u64;
-    let bitboard = bitboard_from_square(square);
-
-    match side {
-        Side::White => {
-            if bitboard & NOT_A_FILE != 0 {
-                attacks |= bitboard_from_square(square - 9);
-            }
+use crate::codegen::magic_index;
+use crate::squares::Square;
+
+// Leaper, slider, between and line tables, along with the magic numbers,
+// relevant occupancy masks and relevant bit counts indexing the slider ones.
+// See `codegen::write_attack_tables`.
+include!(concat!(env!("OUT_DIR"), "/attack_tables.rs"));
+
+/// How the slider attack tables are indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliderBackend {
+    /// Multiply-shift magic numbers, available everywhere.
+    Magic,
+
+    /// BMI2 `pext` instruction, on x86_64 processors supporting it.
+    Pext,
+}
 
-            if bitboard & NOT_H_FILE != 0 {
-                attacks |= bitboard_from_square(square - 7);
-            }
+impl SliderBackend {
+    pub fn is_supported(self) -> bool {
+        match self {
+            SliderBackend::Magic => true,
+            #[cfg(target_arch = "x86_64")]
+            SliderBackend::Pext => is_x86_feature_detected!("bmi2"),
+            #[cfg(not(target_arch = "x86_64"))]
+            SliderBackend::Pext => false,
         }
-        Side::Black => {
-            if bitboard & NOT_A_FILE != 0 {
-                attacks |= bitboard_from_square(square + 7);
-            }
+    }
 
-            if bitboard & NOT_H_FILE != 0 {
-                attacks |= bitboard_from_square(square + 9);
-            }
+    /// The fastest backend supported by the running processor.
+    pub fn detect() -> Self {
+        if SliderBackend::Pext.is_supported() {
+            SliderBackend::Pext
+        } else {
+            SliderBackend::Magic
         }
-        _ => {}
     }
-
-    attacks
 }
 
-fn mask_knight_attacks(square: i32) -> u64 {
-    let mut attacks = 0u64;
-    let bitboard = bitboard_from_square(square);
+/// How the slider attack tables are laid out in memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SliderLayout {
+    /// "Fancy" magics: each square takes `1 << relevant_bits` entries of a shared table.
+    #[default]
+    Packed,
 
-    if bitboard & NOT_A_FILE != 0 {
-        attacks |= bitboard_from_square(square - 17);
-        attacks |= bitboard_from_square(square + 15);
-    }
-    if bitboard & NOT_AB_FILE != 0 {
-        attacks |= bitboard_from_square(square - 10);
-        attacks |= bitboard_from_square(square + 6);
-    }
-    if bitboard & NOT_GH_FILE != 0 {
-        attacks |= bitboard_from_square(square - 6);
-        attacks |= bitboard_from_square(square + 10);
-    }
-    if bitboard & NOT_H_FILE != 0 {
-        attacks |= bitboard_from_square(square - 15);
-        attacks |= bitboard_from_square(square + 17);
-    }
-
-    attacks
+    /// Each square reserves 512 entries for bishops and 4096 for rooks, whatever
+    /// its relevant bits. Only kept to measure what packing gains, see `perft::bench`.
+    Padded,
 }
 
-fn mask_king_attacks(square: i32) -> u64 {
-    let mut attacks = 0u64;
-    let bitboard = bitboard_from_square(square);
-
-    attacks |= bitboard_from_square(square - 8);
-    attacks |= bitboard_from_square(square + 8);
-    if bitboard & NOT_A_FILE != 0 {
-        attacks |= bitboard_from_square(square - 9);
-        attacks |= bitboard_from_square(square - 1);
-        attacks |= bitboard_from_square(square + 7);
-    }
-    if bitboard & NOT_H_FILE != 0 {
-        attacks |= bitboard_from_square(square + 9);
-        attacks |= bitboard_from_square(square + 1);
-        attacks |= bitboard_from_square(square - 7);
-    }
-
-    attacks
+/// Attack tables. They are generated at build time, so this is merely a handle on them,
+/// which only chooses how the slider ones are indexed and laid out.
+#[derive(Debug, Clone, Copy)]
+pub struct Attacks {
+    backend: SliderBackend,
+    layout: SliderLayout,
 }
 
-pub fn mask_bishop_attacks(square: i32) -> u64 {
-    let mut attacks = 0u64;
-
-    let rank = square / 8;
-    let file = square % 8;
-
-    for (i, j) in (1..rank).rev().zip((1..file).rev()) {
-        attacks |= bitboard_from_square(get_square(i, j));
-    }
-
-    for (i, j) in (1..rank).rev().zip(file + 1..7) {
-        attacks |= bitboard_from_square(get_square(i, j));
-    }
+/// Every possible handle, so that `Attacks::global` can switch between them.
+static HANDLES: [Attacks; 4] = [
+    Attacks::handle(SliderBackend::Magic, SliderLayout::Packed),
+    Attacks::handle(SliderBackend::Magic, SliderLayout::Padded),
+    Attacks::handle(SliderBackend::Pext, SliderLayout::Packed),
+    Attacks::handle(SliderBackend::Pext, SliderLayout::Padded),
+];
 
-    for (i, j) in (rank + 1..7).zip((1..file).rev()) {
-        attacks |= bitboard_from_square(get_square(i, j));
-    }
+/// Index of the shared handle in `HANDLES`, out of bounds until it is first used.
+static GLOBAL_HANDLE: AtomicUsize = AtomicUsize::new(usize::MAX);
 
-    for (i, j) in (rank + 1..7).zip(file + 1..7) {
-        attacks |= bitboard_from_square(get_square(i, j));
-    }
+/// Padded copies of `SLIDER_ATTACKS` and `SLIDER_PEXT_ATTACKS`, built on first use.
+static PADDED_ATTACKS: OnceLock<PaddedAttacks> = OnceLock::new();
 
-    attacks
+struct PaddedAttacks {
+    magic: Box<[u64]>,
+    pext: Box<[u64]>,
 }
 
-fn mask_rook_attacks(square: i32) -> u64 {
-    let mut attacks = 0u64;
-
-    let rank = square / 8;
-    let file = square % 8;
-
-    for i in (1..rank).rev() {
-        attacks |= bitboard_from_square(get_square(i, file));
-    }
-
-    for i in (1..file).rev() {
-        attacks |= bitboard_from_square(get_square(rank, i));
-    }
+/// Entries reserved for each square in the padded layout.
+const PADDED_BISHOP_ENTRIES: usize = 512;
+const PADDED_ROOK_ENTRIES: usize = 4096;
 
-    for i in rank + 1..7 {
-        attacks |= bitboard_from_square(get_square(i, file));
-    }
+/// Rook tables come after the bishop ones in the padded layout.
+const PADDED_ROOK_BASE: usize = 64 * PADDED_BISHOP_ENTRIES;
 
-    for i in file + 1..7 {
-        attacks |= bitboard_from_square(get_square(rank, i));
+impl Default for Attacks {
+    fn default() -> Self {
+        Self::new()
     }
-
-    attacks
 }
 
-fn bishop_attacks_on_the_fly(square: i32, blocker: u64) -> u64 {
-    let mut attacks = 0u64;
-
-    let rank = square / 8;
-    let file = square % 8;
-
-    for (i, j) in (0..rank).rev().zip((0..file).rev()) {
-        let curr_square = get_square(i, j);
-        attacks |= bitboard_from_square(curr_square);
-        if get_bit(blocker, curr_square) {
-            break;
+impl Attacks {
+    const fn handle(backend: SliderBackend, layout: SliderLayout) -> Self {
+        Self { backend, layout }
+    }
+
+    /// Shared handle, using the backend detected on first use
+    /// unless `set_global` was called before.
+    pub fn global() -> &'static Self {
+        match HANDLES.get(GLOBAL_HANDLE.load(Ordering::Relaxed)) {
+            Some(attacks) => attacks,
+            None => {
+                Self::new().set_global();
+                Self::global()
+            }
         }
     }
 
-    for (i, j) in (0..rank).rev().zip(file + 1..8) {
-        let curr_square = get_square(i, j);
-        attacks |= bitboard_from_square(curr_square);
-        if get_bit(blocker, curr_square) {
-            break;
-        }
+    /// Makes this handle the one returned by `global`, e.g. to compare layouts.
+    pub fn set_global(self) {
+        let index = HANDLES
+            .iter()
+            .position(|attacks| attacks.backend == self.backend && attacks.layout == self.layout)
+            .unwrap();
+        GLOBAL_HANDLE.store(index, Ordering::Relaxed);
     }
 
-    for (i, j) in (rank + 1..8).zip((0..file).rev()) {
-        let curr_square = get_square(i, j);
-        attacks |= bitboard_from_square(curr_square);
-        if get_bit(blocker, curr_square) {
-            break;
-        }
+    pub fn new() -> Self {
+        Self::handle(SliderBackend::detect(), SliderLayout::Packed)
     }
 
-    for (i, j) in (rank + 1..8).zip(file + 1..8) {
-        let curr_square = get_square(i, j);
-        attacks |= bitboard_from_square(curr_square);
-        if get_bit(blocker, curr_square) {
-            break;
-        }
+    /// None if the running processor does not support the backend.
+    pub fn with_backend(backend: SliderBackend) -> Option<Self> {
+        backend
+            .is_supported()
+            .then_some(Self::handle(backend, SliderLayout::Packed))
     }
 
-    attacks
-}
-
-fn rook_attacks_on_the_fly(square: i32, blocker: u64) -> u64 {
-    let mut attacks = 0u64;
-
-    let rank = square / 8;
-    let file = square % 8;
-
-    for i in (0..rank).rev() {
-        let curr_square = get_square(i, file);
-        attacks |= bitboard_from_square(curr_square);
-        if get_bit(blocker, curr_square) {
-            break;
-        }
+    pub fn with_layout(self, layout: SliderLayout) -> Self {
+        Self { layout, ..self }
     }
 
-    for i in (0..file).rev() {
-        let curr_square = get_square(rank, i);
-        attacks |= bitboard_from_square(curr_square);
-        if get_bit(blocker, curr_square) {
-            break;
-        }
+    pub fn backend(&self) -> SliderBackend {
+        self.backend
     }
 
-    for i in rank + 1..8 {
-        let curr_square = get_square(i, file);
-        attacks |= bitboard_from_square(curr_square);
-        if get_bit(blocker, curr_square) {
-            break;
-        }
+    pub fn layout(&self) -> SliderLayout {
+        self.layout
     }
 
-    for i in file + 1..8 {
-        let curr_square = get_square(rank, i);
-        attacks |= bitboard_from_square(curr_square);
-        if get_bit(blocker, curr_square) {
-            break;
+    pub fn get_pawn_attacks(&self, square: Square, side: Side) -> Bitboard {
+        match side {
+            Side::White => Bitboard(WHITE_PAWN_ATTACKS[square.index()]),
+            Side::Black => Bitboard(BLACK_PAWN_ATTACKS[square.index()]),
+            Side::Both => Bitboard::EMPTY,
         }
     }
 
-    attacks
-}
-
-fn generate_pawn_attacks() -> [[u64; 64]; 2] {
-    let mut result = [[0u64; 64]; 2];
-
-    for square in 0..64 {
-        result[0][square] = mask_pawn_attacks(square as i32, Side::White);
-        result[1][square] = mask_pawn_attacks(square as i32, Side::Black);
-    }
-
-    result
-}
-
-fn generate_knight_attacks() -> [u64; 64] {
-    let mut result = [0u64; 64];
-
-    for square in 0..64 {
-        result[square] = mask_knight_attacks(square as i32);
+    pub fn get_knight_attacks(&self, square: Square) -> Bitboard {
+        Bitboard(KNIGHT_ATTACKS[square.index()])
     }
 
-    result
-}
-
-fn generate_king_attacks() -> [u64; 64] {
-    let mut result = [0u64; 64];
-
-    for square in 0..64 {
-        result[square] = mask_king_attacks(square as i32);
+    pub fn get_king_attacks(&self, square: Square) -> Bitboard {
+        Bitboard(KING_ATTACKS[square.index()])
     }
 
-    result
-}
-
-fn generate_bishop_occupancies() -> [u32; 64] {
-    let mut result = [0u32; 64];
+    pub fn get_bishop_attacks(&self, square: Square, occupancy: Bitboard) -> Bitboard {
+        let index = square.index();
 
-    for square in 0..64 {
-        result[square] = bit_count(mask_bishop_attacks(square as i32));
-    }
-
-    result
-}
-
-fn generate_rook_occupancies() -> [u32; 64] {
-    let mut result = [0u32; 64];
-
-    for square in 0..64 {
-        result[square] = bit_count(mask_rook_attacks(square as i32));
-    }
-
-    result
-}
-
-fn set_occupancy(index: usize, bits_in_mask: u32, attack_mask: u64) -> u64 {
-    let mut occupancy = 0u64;
-    let mut mask = attack_mask;
-
-    for count in 0..bits_in_mask {
-        let square = lsb_index(mask) as i32;
-        mask = pop_bit(mask, square);
+        let table_index = if self.backend == SliderBackend::Pext {
+            pext::index(occupancy.0, BISHOP_MASKS[index])
+        } else {
+            magic_index(
+                occupancy.0,
+                BISHOP_MASKS[index],
+                BISHOP_MAGICS[index],
+                BISHOP_RELEVANT_BITS[index],
+            )
+        };
 
-        if index & (1 << count) != 0 {
-            occupancy |= bitboard_from_square(square);
-        }
+        self.slider_attacks(
+            BISHOP_OFFSETS[index] as usize,
+            index * PADDED_BISHOP_ENTRIES,
+            table_index,
+        )
     }
 
-    occupancy
-}
+    pub fn get_rook_attacks(&self, square: Square, occupancy: Bitboard) -> Bitboard {
+        let index = square.index();
 
-fn find_magic_number(
-    random_state: u32,
-    square: i32,
-    relevant_bits: u32,
-    is_bishop: bool,
-) -> (u64, u32) {
-    use crate::rand::next_magic_candidate;
-
-    // FIXME: I do not like that
-    let mut occupancies = [0u64; 4096];
-    let mut attacks = [0u64; 4096];
-    let mut used_attacks: [u64; 4096]; // [0u64; 4096];
-    let mut last_rand = random_state;
-
-    let attack_mask = if is_bishop {
-        mask_bishop_attacks(square)
-    } else {
-        mask_rook_attacks(square)
-    };
-
-    let occupancy_indices = 1 << relevant_bits;
-
-    for index in 0..occupancy_indices {
-        occupancies[index] = set_occupancy(index, relevant_bits, attack_mask);
-        attacks[index] = if is_bishop {
-            bishop_attacks_on_the_fly(square, occupancies[index])
+        let table_index = if self.backend == SliderBackend::Pext {
+            pext::index(occupancy.0, ROOK_MASKS[index])
         } else {
-            rook_attacks_on_the_fly(square, occupancies[index])
+            magic_index(
+                occupancy.0,
+                ROOK_MASKS[index],
+                ROOK_MAGICS[index],
+                ROOK_RELEVANT_BITS[index],
+            )
         };
-    }
-
-    loop {
-        let (magic, rnd) = next_magic_candidate(last_rand);
-        last_rand = rnd;
 
-        // Ignore inappropriate magics
-        let mask = attack_mask.wrapping_mul(magic);
-        if bit_count(mask & 0xFF00000000000000) < 6 {
-            continue;
-        }
-
-        used_attacks = [0u64; 4096];
-        let mut failed = false;
-
-        for index in 0..occupancy_indices {
-            let tested_magic = occupancies[index].wrapping_mul(magic);
-            if let Some(magic_index) = tested_magic.checked_shr(64 - relevant_bits as u32) {
-                let magic_index = magic_index as usize;
-                if used_attacks[magic_index] == 0 {
-                    used_attacks[magic_index] = attacks[index];
-                } else if used_attacks[magic_index] != attacks[index] {
-                    failed = true;
-                }
-            } else {
-                failed = true;
+        self.slider_attacks(
+            ROOK_OFFSETS[index] as usize,
+            PADDED_ROOK_BASE + index * PADDED_ROOK_ENTRIES,
+            table_index,
+        )
+    }
+
+    /// Entry `table_index` of the square table starting at `packed_offset`
+    /// or `padded_offset`, depending on the layout.
+    #[inline]
+    fn slider_attacks(
+        &self,
+        packed_offset: usize,
+        padded_offset: usize,
+        table_index: usize,
+    ) -> Bitboard {
+        let pext = self.backend == SliderBackend::Pext;
+
+        Bitboard(match self.layout {
+            SliderLayout::Packed if pext => SLIDER_PEXT_ATTACKS[packed_offset + table_index],
+            SliderLayout::Packed => SLIDER_ATTACKS[packed_offset + table_index],
+            SliderLayout::Padded => {
+                let padded = PADDED_ATTACKS.get_or_init(|| PaddedAttacks {
+                    magic: pad_tables(&SLIDER_ATTACKS),
+                    pext: pad_tables(&SLIDER_PEXT_ATTACKS),
+                });
+                let attacks = if pext { &padded.pext } else { &padded.magic };
+                attacks[padded_offset + table_index]
             }
-
-            if failed {
-                break;
-            }
-        }
-
-        if !failed {
-            return (magic, last_rand);
-        }
+        })
     }
-}
 
-fn generate_bishop_magic_numbers(occupancies: &[u32; 64]) -> [u64; 64] {
-    let mut result = [0u64; 64];
-
-    let mut random_state = 1804289383;
-
-    for square in 0..64 {
-        result[square] = {
-            let (magic, rnd) =
-                find_magic_number(random_state, square as i32, occupancies[square], true);
-            random_state = rnd;
-            magic
-        };
+    pub fn get_queen_attacks(&self, square: Square, occupancy: Bitboard) -> Bitboard {
+        self.get_bishop_attacks(square, occupancy) | self.get_rook_attacks(square, occupancy)
     }
 
-    result
-}
-
-fn generate_rook_magic_numbers(occupancies: &[u32; 64]) -> [u64; 64] {
-    let mut result = [0u64; 64];
-
-    let mut random_state = 1804289383;
-
-    for square in 0..64 {
-        result[square] = {
-            let (magic, rnd) =
-                find_magic_number(random_state, square as i32, occupancies[square], false);
-            random_state = rnd;
-            magic
-        };
+    /// Squares strictly between `square_a` and `square_b`, if they are aligned.
+    pub fn get_between(&self, square_a: Square, square_b: Square) -> Bitboard {
+        Bitboard(BETWEEN[square_a.index() * 64 + square_b.index()])
     }
 
-    result
-}
-
-#[derive(Debug)]
-struct MagicNumbers {
-    bishop: [u64; 64],
-    rook: [u64; 64],
+    /// Line going through `square_a` and `square_b`, if they are aligned.
+    pub fn get_line(&self, square_a: Square, square_b: Square) -> Bitboard {
+        Bitboard(LINE[square_a.index() * 64 + square_b.index()])
+    }
 }
 
-impl MagicNumbers {
-    pub fn new(occupancies: &Occupancies) -> Self {
-        let mut bishop = [0u64; 64];
-        let mut random_state = 1804289383;
-
+/// Copies a packed slider table into the padded layout, the way the tables were
+/// stored before being packed.
+fn pad_tables(packed: &[u64]) -> Box<[u64]> {
+    let mut padded = vec![0; PADDED_ROOK_BASE + 64 * PADDED_ROOK_ENTRIES];
+
+    for (offsets, relevant_bits, padded_base, entries) in [
+        (
+            &BISHOP_OFFSETS,
+            &BISHOP_RELEVANT_BITS,
+            0,
+            PADDED_BISHOP_ENTRIES,
+        ),
+        (
+            &ROOK_OFFSETS,
+            &ROOK_RELEVANT_BITS,
+            PADDED_ROOK_BASE,
+            PADDED_ROOK_ENTRIES,
+        ),
+    ] {
         for square in 0..64 {
-            bishop[square] = {
-                let (magic, rnd) = find_magic_number(
-                    random_state,
-                    square as i32,
-                    occupancies.bishop[square],
-                    true,
-                );
-                random_state = rnd;
-                magic
-            };
+            let offset = offsets[square] as usize;
+            let len = 1 << relevant_bits[square];
+            let padded_offset = padded_base + square * entries;
+            padded[padded_offset..padded_offset + len]
+                .copy_from_slice(&packed[offset..offset + len]);
         }
-
-        let mut rook = [0u64; 64];
-
-        for square in 0..64 {
-            rook[square] = {
-                let (magic, rnd) =
-                    find_magic_number(random_state, square as i32, occupancies.rook[square], false);
-                random_state = rnd;
-                magic
-            };
-        }
-
-        Self { bishop, rook }
     }
-}
 
-#[derive(Debug)]
-struct SlidingMasks {
-    bishop: [u64; 64],
-    rook: [u64; 64],
+    padded.into_boxed_slice()
 }
 
-impl SlidingMasks {
-    fn new() -> Self {
-        let mut bishop = [0u64; 64];
-        let mut rook = [0u64; 64];
+#[cfg(target_arch = "x86_64")]
+mod pext {
+    use std::arch::x86_64::_pext_u64;
 
-        for square in 0..64 {
-            bishop[square] = mask_bishop_attacks(square as i32);
-            rook[square] = mask_rook_attacks(square as i32);
-        }
-
-        Self { bishop, rook }
+    /// Only called by `Attacks` instances using the PEXT backend, which can only be
+    /// built when BMI2 is supported.
+    #[inline]
+    pub fn index(occupancy: u64, mask: u64) -> usize {
+        // SAFETY: see above.
+        unsafe { pext(occupancy, mask) as usize }
     }
-}
 
-#[derive(Debug)]
-struct Occupancies {
-    bishop: [u32; 64],
-    rook: [u32; 64],
-}
-
-impl Occupancies {
-    fn new() -> Self {
-        Self {
-            bishop: generate_bishop_occupancies(),
-            rook: generate_rook_occupancies(),
-        }
+    #[target_feature(enable = "bmi2")]
+    fn pext(occupancy: u64, mask: u64) -> u64 {
+        _pext_u64(occupancy, mask)
     }
 }
 
-pub struct Attacks {
-    pawn: [[u64; 64]; 2],
-    knight: [u64; 64],
-    king: [u64; 64],
-    rook: Vec<u64>,
-    bishop: Vec<u64>,
-
-    sliding_masks: SlidingMasks,
-    occupancies: Occupancies,
-}
-
-fn time_as_ms(d: Duration) -> f64 {
-    d.as_micros() as f64 * 1e-3
-}
-
-impl Attacks {
-    pub fn print_magic_numbers() {
-        let occupancies = Occupancies::new();
-        let magic_numbers = MagicNumbers::new(&occupancies);
-
-        println!("Bishop magics: {:?}", magic_numbers.bishop);
-        println!("Rook magics: {:?}", magic_numbers.rook);
+#[cfg(not(target_arch = "x86_64"))]
+mod pext {
+    pub fn index(_occupancy: u64, _mask: u64) -> usize {
+        unreachable!("PEXT is only available on x86_64")
     }
+}
 
-    pub fn new() -> Self {
-        println!("Start generation... ");
-        let timer = Instant::now();
-
-        let occupancies = Occupancies::new();
-        let occupancies_time = timer.elapsed();
-        println!("  Occupancies ok ({}ms)... ", time_as_ms(occupancies_time));
-
-        let sliding_masks = SlidingMasks::new();
-        let sliding_masks_time = timer.elapsed();
-        println!(
-            "  Sliding masks ok ({}ms)... ",
-            time_as_ms(sliding_masks_time - occupancies_time)
-        );
-
-        let pawn = generate_pawn_attacks();
-        let pawn_time = timer.elapsed();
-        println!(
-            "  Pawn attacks ok ({}ms)... ",
-            time_as_ms(pawn_time - sliding_masks_time)
-        );
-
-        let knight = generate_knight_attacks();
-        let knight_time = timer.elapsed();
-        println!(
-            "  Knight attacks ok ({}ms)...",
-            time_as_ms(knight_time - pawn_time)
-        );
-
-        let king = generate_king_attacks();
-        let king_time = timer.elapsed();
-        println!(
-            "  King attacks ok ({}ms)...",
-            time_as_ms(king_time - knight_time)
-        );
-
-        let mut bishop = Vec::new();
-        bishop.resize(512 * 64, 0u64);
-
-        for square in 0..64 {
-            let bishop_attack_mask = sliding_masks.bishop[square];
-            let bishop_relevant_bit_count = bit_count(bishop_attack_mask);
-            let bishop_occupancy_indices = 1 << bishop_relevant_bit_count;
-
-            for index in 0..bishop_occupancy_indices {
-                let occupancy = set_occupancy(index, bishop_relevant_bit_count, bishop_attack_mask);
-                let magic_index = occupancy.wrapping_mul(BISHOP_MAGIC_NUMBERS[square])
-                    >> (64 - occupancies.bishop[square]);
-
-                bishop[square * 512 + magic_index as usize] =
-                    bishop_attacks_on_the_fly(square as i32, occupancy);
-            }
-        }
-        let bishop_time = timer.elapsed();
-        println!(
-            "  Bishop attacks ok ({}ms)...",
-            time_as_ms(bishop_time - king_time)
-        );
-
-        let mut rook = Vec::new();
-        rook.resize(4096 * 64, 0u64);
-
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::*;
+
+    /// Every occupancy of the relevant mask must land on an entry holding its own
+    /// attacks: occupancies may only share an index when their attacks are the same.
+    fn check_magics(
+        masks: &[u64; 64],
+        magics: &[u64; 64],
+        relevant_bits: &[u32; 64],
+        offsets: &[u32; 64],
+        attacks_on_the_fly: fn(i32, u64) -> u64,
+    ) {
         for square in 0..64 {
-            let rook_attack_mask = sliding_masks.rook[square];
-            let rook_relevant_bit_count = bit_count(rook_attack_mask);
-            let rook_occupancy_indices = 1 << rook_relevant_bit_count;
-
-            for index in 0..rook_occupancy_indices {
-                let occupancy = set_occupancy(index, rook_relevant_bit_count, rook_attack_mask);
-                let magic_index = occupancy.wrapping_mul(ROOK_MAGIC_NUMBERS[square])
-                    >> (64 - occupancies.rook[square]);
-
-                rook[square * 4096 + magic_index as usize] =
-                    rook_attacks_on_the_fly(square as i32, occupancy);
+            let mask = masks[square];
+            assert_eq!(mask.count_ones(), relevant_bits[square]);
+
+            for index in 0..1 << relevant_bits[square] {
+                let occupancy = set_occupancy(index, mask);
+                let magic_index =
+                    magic_index(occupancy, mask, magics[square], relevant_bits[square]);
+
+                assert_eq!(
+                    SLIDER_ATTACKS[offsets[square] as usize + magic_index],
+                    attacks_on_the_fly(square as i32, occupancy),
+                    "collision for square {} and occupancy {:#x}",
+                    square,
+                    occupancy
+                );
             }
         }
-        let rook_time = timer.elapsed();
-        println!(
-            "  Rook attacks ok ({}ms)...",
-            time_as_ms(rook_time - bishop_time)
-        );
-
-        Self {
-            pawn,
-            knight,
-            king,
-            bishop,
-            rook,
-            sliding_masks,
-            occupancies,
-        }
     }
 
-    pub fn get_pawn_attacks(&self, square: i32, side: Side) -> u64 {
-        self.pawn[side as usize][square as usize]
-    }
-
-    pub fn get_knight_attacks(&self, square: i32) -> u64 {
-        self.knight[square as usize]
+    #[test]
+    fn bishop_magics_are_collision_free() {
+        check_magics(
+            &BISHOP_MASKS,
+            &BISHOP_MAGICS,
+            &BISHOP_RELEVANT_BITS,
+            &BISHOP_OFFSETS,
+            bishop_attacks_on_the_fly,
+        );
     }
 
-    pub fn get_king_attacks(&self, square: i32) -> u64 {
-        self.king[square as usize]
+    #[test]
+    fn rook_magics_are_collision_free() {
+        check_magics(
+            &ROOK_MASKS,
+            &ROOK_MAGICS,
+            &ROOK_RELEVANT_BITS,
+            &ROOK_OFFSETS,
+            rook_attacks_on_the_fly,
+        );
     }
 
-    pub fn get_bishop_attacks(&self, square: i32, occupancy: u64) -> u64 {
-        let mut occupancy_idx = occupancy;
+    #[test]
+    fn pext_and_magic_backends_agree() {
+        if !SliderBackend::Pext.is_supported() {
+            eprintln!("skipping pext_and_magic_backends_agree: BMI2 is not available");
+            return;
+        }
 
-        let index = square as usize;
+        let pext = Attacks::with_backend(SliderBackend::Pext).unwrap();
+        let magic = Attacks::with_backend(SliderBackend::Magic).unwrap();
 
-        occupancy_idx &= self.sliding_masks.bishop[index];
-        occupancy_idx = occupancy_idx.wrapping_mul(BISHOP_MAGIC_NUMBERS[index]);
-        occupancy_idx >>= 64 - self.occupancies.bishop[index];
+        let mut random_state = 1804289383;
+        for square in Square::all() {
+            for _ in 0..1000 {
+                let (random, rnd) = crate::rand::get_random_u64_number(random_state);
+                random_state = rnd;
+                let occupancy = Bitboard(random);
 
-        self.bishop[index * 512 + occupancy_idx as usize]
+                assert_eq!(
+                    pext.get_bishop_attacks(square, occupancy),
+                    magic.get_bishop_attacks(square, occupancy)
+                );
+                assert_eq!(
+                    pext.get_rook_attacks(square, occupancy),
+                    magic.get_rook_attacks(square, occupancy)
+                );
+            }
+        }
     }
 
-    pub fn get_rook_attacks(&self, square: i32, occupancy: u64) -> u64 {
-        let mut occupancy_idx = occupancy;
+    #[test]
+    fn packed_and_padded_layouts_agree() {
+        let packed = Attacks::with_backend(SliderBackend::Magic).unwrap();
+        let padded = packed.with_layout(SliderLayout::Padded);
 
-        let index = square as usize;
-
-        occupancy_idx &= self.sliding_masks.rook[index];
-        occupancy_idx = occupancy_idx.wrapping_mul(ROOK_MAGIC_NUMBERS[index]);
-        occupancy_idx >>= 64 - self.occupancies.rook[index];
-
-        self.rook[index * 4096 + occupancy_idx as usize]
-    }
+        let mut random_state = 1804289383;
+        for square in Square::all() {
+            for _ in 0..1000 {
+                let (random, rnd) = crate::rand::get_random_u64_number(random_state);
+                random_state = rnd;
+                let occupancy = Bitboard(random);
 
-    pub fn get_queen_attacks(&self, square: i32, occupancy: u64) -> u64 {
-        self.get_bishop_attacks(square, occupancy) | self.get_rook_attacks(square, occupancy)
+                assert_eq!(
+                    packed.get_bishop_attacks(square, occupancy),
+                    padded.get_bishop_attacks(square, occupancy)
+                );
+                assert_eq!(
+                    packed.get_rook_attacks(square, occupancy),
+                    padded.get_rook_attacks(square, occupancy)
+                );
+            }
+        }
     }
 }
@@ -1,54 +1,300 @@
 #![allow(dead_code)]
 
+use std::fmt;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
 use crate::codegen;
+use crate::squares::Square;
+
+/// A set of squares, one bit per square (bit 0 is a8, bit 63 is h1).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bitboard(pub u64);
+
+pub const NOT_A_FILE: Bitboard = Bitboard(codegen::not_a_file());
+pub const NOT_H_FILE: Bitboard = Bitboard(codegen::not_h_file());
+pub const NOT_AB_FILE: Bitboard = Bitboard(codegen::not_ab_file());
+pub const NOT_GH_FILE: Bitboard = Bitboard(codegen::not_gh_file());
+
+/// Directions a bitboard can be shifted in. North is towards the 8th rank,
+/// east towards the h-file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl Bitboard {
+    pub const EMPTY: Self = Self(0);
+    pub const FULL: Self = Self(!0);
+
+    pub const fn from_square(square: Square) -> Self {
+        Self(1 << square.index())
+    }
+
+    pub const fn contains(self, square: Square) -> bool {
+        self.0 & Self::from_square(square).0 != 0
+    }
+
+    pub const fn with(self, square: Square) -> Self {
+        Self(self.0 | Self::from_square(square).0)
+    }
+
+    pub const fn without(self, square: Square) -> Self {
+        Self(self.0 & !Self::from_square(square).0)
+    }
+
+    pub const fn toggled(self, square: Square) -> Self {
+        Self(self.0 ^ Self::from_square(square).0)
+    }
 
-pub const NOT_A_FILE: u64 = codegen::not_a_file();
-pub const NOT_H_FILE: u64 = codegen::not_h_file();
-pub const NOT_AB_FILE: u64 = codegen::not_ab_file();
-pub const NOT_GH_FILE: u64 = codegen::not_gh_file();
+    pub const fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn collides(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// The square of least significant bit, None for an empty bitboard.
+    pub const fn lsb(self) -> Option<Square> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(Square::new(self.0.trailing_zeros() as u8))
+        }
+    }
+
+    /// Moves every square one step in the given direction.
+    /// Squares falling off the board are dropped, they do not wrap to the other side.
+    pub const fn shift(self, direction: Direction) -> Self {
+        match direction {
+            Direction::North => Self(self.0 >> 8),
+            Direction::South => Self(self.0 << 8),
+            Direction::East => Self((self.0 << 1) & NOT_A_FILE.0),
+            Direction::West => Self((self.0 >> 1) & NOT_H_FILE.0),
+            Direction::NorthEast => Self((self.0 >> 7) & NOT_A_FILE.0),
+            Direction::NorthWest => Self((self.0 >> 9) & NOT_H_FILE.0),
+            Direction::SouthEast => Self((self.0 << 9) & NOT_A_FILE.0),
+            Direction::SouthWest => Self((self.0 << 7) & NOT_H_FILE.0),
+        }
+    }
+}
 
-pub const fn bitboard_from_square(square: i32) -> u64 {
-    if let Some(v) = (1 as u64).checked_shl(square as u32) {
-        v
-    } else {
-        0
+impl From<Square> for Bitboard {
+    fn from(square: Square) -> Self {
+        Self::from_square(square)
     }
 }
 
-pub fn make_blocker(squares: Vec<i32>) -> u64 {
-    let mut result = 0u64;
+/// Iterates over the squares of the set, from a8 to h1, emptying the bitboard.
+impl Iterator for Bitboard {
+    type Item = Square;
 
-    for square in squares {
-        result |= bitboard_from_square(square)
+    fn next(&mut self) -> Option<Square> {
+        let square = self.lsb()?;
+        self.0 &= self.0 - 1;
+        Some(square)
     }
 
-    result
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.count() as usize;
+        (count, Some(count))
+    }
 }
 
-pub const fn get_bit(bitboard: u64, square: i32) -> bool {
-    bitboard & bitboard_from_square(square) != 0
+impl FromIterator<Square> for Bitboard {
+    fn from_iter<I: IntoIterator<Item = Square>>(squares: I) -> Self {
+        squares
+            .into_iter()
+            .fold(Self::EMPTY, |bitboard, square| bitboard.with(square))
+    }
 }
 
-pub const fn toggle_bit(bitboard: u64, square: i32) -> u64 {
-    bitboard ^ bitboard_from_square(square)
+impl BitAnd for Bitboard {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
 }
 
-pub const fn pop_bit(bitboard: u64, square: i32) -> u64 {
-    bitboard & !bitboard_from_square(square)
+impl BitOr for Bitboard {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
 }
 
-pub const fn bit_count(bitboard: u64) -> u32 {
-    bitboard.count_ones()
+impl BitXor for Bitboard {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self {
+        Self(self.0 ^ rhs.0)
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
 }
 
-pub const fn bits_collide(bitboard_a: u64, bitboard_b: u64) -> bool {
-    bitboard_a & bitboard_b != 0
+impl BitXorAssign for Bitboard {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.0 ^= rhs.0;
+    }
 }
 
-pub const fn lsb(bitboard: u64) -> u64 {
-    bitboard & 0u64.wrapping_sub(bitboard)
+/// Board diagram, 8th rank on top, with '×' for the squares of the set.
+impl fmt::Display for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "      ---------------")?;
+        for rank in 0..8 {
+            write!(f, " {}  |", 8 - rank)?;
+            for file in 0..8 {
+                let square = Square::from_rank_file(rank, file);
+                write!(f, " {}", if self.contains(square) { '×' } else { '·' })?;
+            }
+            writeln!(f, " |")?;
+        }
+        writeln!(f, "      ---------------")?;
+        write!(f, "      a b c d e f g h")
+    }
 }
 
-pub const fn lsb_index(bitboard: u64) -> u32 {
-    bitboard.trailing_zeros()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::squares::*;
+
+    const DIRECTIONS: [(Direction, i8, i8); 8] = [
+        (Direction::North, -1, 0),
+        (Direction::South, 1, 0),
+        (Direction::East, 0, 1),
+        (Direction::West, 0, -1),
+        (Direction::NorthEast, -1, 1),
+        (Direction::NorthWest, -1, -1),
+        (Direction::SouthEast, 1, 1),
+        (Direction::SouthWest, 1, -1),
+    ];
+
+    #[test]
+    fn file_masks_leave_out_the_edge_files() {
+        for (mask, excluded_files) in [
+            (NOT_A_FILE, 0..1),
+            (NOT_H_FILE, 7..8),
+            (NOT_AB_FILE, 0..2),
+            (NOT_GH_FILE, 6..8),
+        ] {
+            for square in Square::all() {
+                assert_eq!(
+                    mask.contains(square),
+                    !excluded_files.contains(&square.file()),
+                    "{}",
+                    square
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shifts_do_not_wrap_around_the_board() {
+        for square in Square::all() {
+            for (direction, rank_step, file_step) in DIRECTIONS {
+                let rank = square.rank() as i8 + rank_step;
+                let file = square.file() as i8 + file_step;
+                let expected = if (0..8).contains(&rank) && (0..8).contains(&file) {
+                    Bitboard::from_square(Square::from_rank_file(rank as u8, file as u8))
+                } else {
+                    Bitboard::EMPTY
+                };
+
+                assert_eq!(
+                    Bitboard::from_square(square).shift(direction),
+                    expected,
+                    "{} {:?}",
+                    square,
+                    direction
+                );
+            }
+        }
+
+        // Whole files and ranks are shifted at once.
+        assert_eq!(Bitboard::FULL.shift(Direction::East), NOT_A_FILE);
+        assert_eq!(Bitboard::FULL.shift(Direction::West), NOT_H_FILE);
+        assert_eq!(Bitboard::FULL.shift(Direction::North).count(), 56);
+    }
+
+    #[test]
+    fn squares_are_iterated_from_a8_to_h1() {
+        let bitboard: Bitboard = [h1, e4, a8, c7].into_iter().collect();
+
+        assert_eq!(bitboard.count(), 4);
+        assert_eq!(bitboard.size_hint(), (4, Some(4)));
+        assert_eq!(bitboard.lsb(), Some(a8));
+        assert_eq!(bitboard.collect::<Vec<_>>(), vec![a8, c7, e4, h1]);
+
+        assert_eq!(Bitboard::EMPTY.lsb(), None);
+        assert_eq!(Bitboard::FULL.count(), 64);
+        assert!(Bitboard::FULL.eq(Square::all()));
+    }
+
+    #[test]
+    fn single_squares_are_added_and_removed() {
+        let bitboard = Bitboard::EMPTY.with(e4).with(d5);
+
+        assert!(bitboard.contains(e4) && bitboard.contains(d5));
+        assert_eq!(bitboard.without(e4), Bitboard::from(d5));
+        assert_eq!(
+            bitboard.toggled(e4).toggled(a1),
+            Bitboard::from(d5).with(a1)
+        );
+        assert!(bitboard.collides(Bitboard::from(d5)));
+        assert!(!bitboard.collides(!bitboard));
+    }
+
+    #[test]
+    fn bitboards_are_displayed_as_a_board() {
+        let bitboard: Bitboard = [a8, e4, h1].into_iter().collect();
+
+        let expected = [
+            "      ---------------",
+            " 8  | × · · · · · · · |",
+            " 7  | · · · · · · · · |",
+            " 6  | · · · · · · · · |",
+            " 5  | · · · · · · · · |",
+            " 4  | · · · · × · · · |",
+            " 3  | · · · · · · · · |",
+            " 2  | · · · · · · · · |",
+            " 1  | · · · · · · · × |",
+            "      ---------------",
+            "      a b c d e f g h",
+        ];
+        assert_eq!(bitboard.to_string(), expected.join("\n"));
+    }
 }
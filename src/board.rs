@@ -1,13 +1,13 @@
 use num_enum::FromPrimitive;
 use phf::phf_map;
 
-use crate::attacks::Attacks;
 use crate::bitboard::*;
-use crate::codegen::get_square;
+use crate::eval::Evaluation;
 use crate::move_generator::*;
 use crate::squares::*;
+use crate::zobrist::ZOBRIST_KEYS;
 
-#[derive(Clone, Copy, Eq, PartialEq, FromPrimitive)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, FromPrimitive)]
 #[repr(i32)]
 pub enum Piece {
     #[num_enum(default)]
@@ -19,7 +19,18 @@ pub enum Piece {
     King,
 }
 
-#[derive(Clone, Copy)]
+pub const PIECES: [Piece; 6] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+    Piece::King,
+];
+
+pub const PIECE_COUNT: usize = PIECES.len();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SidedPiece {
     WhitePawn,
     WhiteKnight,
@@ -35,13 +46,47 @@ pub enum SidedPiece {
     BlackKing,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Side {
     White,
     Black,
     Both,
 }
 
+/// Indexed like `Board::pieces`.
+const SIDED_PIECES: [SidedPiece; 12] = [
+    SidedPiece::WhitePawn,
+    SidedPiece::WhiteKnight,
+    SidedPiece::WhiteBishop,
+    SidedPiece::WhiteRook,
+    SidedPiece::WhiteQueen,
+    SidedPiece::WhiteKing,
+    SidedPiece::BlackPawn,
+    SidedPiece::BlackKnight,
+    SidedPiece::BlackBishop,
+    SidedPiece::BlackRook,
+    SidedPiece::BlackQueen,
+    SidedPiece::BlackKing,
+];
+
+impl SidedPiece {
+    pub fn new(piece: Piece, side: Side) -> Self {
+        SIDED_PIECES[piece as usize + side as usize * PIECE_COUNT]
+    }
+
+    pub fn piece(self) -> Piece {
+        PIECES[self as usize % PIECE_COUNT]
+    }
+
+    pub fn side(self) -> Side {
+        if (self as usize) < PIECE_COUNT {
+            Side::White
+        } else {
+            Side::Black
+        }
+    }
+}
+
 pub enum Castling {
     WhiteKing = 1 << 0,
     WhiteQueen = 1 << 1,
@@ -49,6 +94,17 @@ pub enum Castling {
     BlackQueen = 1 << 3,
 }
 
+/// Castling right (a `Castling` bit) of `side` on the king side or the queen side.
+pub fn castling_right(side: Side, king_side: bool) -> u8 {
+    1 << (side as usize * 2 + !king_side as usize)
+}
+
+/// Rook initial square of each castling right, in standard chess.
+const STANDARD_CASTLING_ROOKS: [Square; 4] = [h1, a1, h8, a8];
+
+/// FEN character of each piece, indexed like `Board::pieces`
+const PIECE_TO_ASCII: [char; 12] = ['P', 'N', 'B', 'R', 'Q', 'K', 'p', 'n', 'b', 'r', 'q', 'k'];
+
 /// Map each ASCII character with a piece type
 /// This is especially useful for FEN parsing
 const ASCII_TO_PIECE: phf::Map<char, SidedPiece> = phf_map! {
@@ -66,6 +122,96 @@ const ASCII_TO_PIECE: phf::Map<char, SidedPiece> = phf_map! {
     'k' => SidedPiece::BlackKing,
 };
 
+/// Reasons for which a FEN string can be rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    /// One of the mandatory fields is missing
+    MissingField(&'static str),
+    /// The piece placement does not describe 8 ranks
+    BadRankCount(usize),
+    /// A rank does not describe exactly 8 squares
+    BadRankLength {
+        rank: usize,
+        length: i32,
+    },
+    UnknownPiece(char),
+    InvalidSideToMove(String),
+    /// A side does not have exactly one king
+    BadKingCount {
+        side: Side,
+        count: u32,
+    },
+    /// Pawns can never stand on the first or last rank
+    PawnOnBackRank(Square),
+    InvalidCastling(String),
+    /// The en-passant field is not a square name
+    InvalidEnPassant(String),
+    /// The en-passant square cannot have been created by the last move
+    ImpossibleEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {} field", field),
+            FenError::BadRankCount(count) => write!(f, "expected 8 ranks, found {}", count),
+            FenError::BadRankLength { rank, length } => {
+                write!(f, "rank {} describes {} squares instead of 8", rank, length)
+            }
+            FenError::UnknownPiece(c) => write!(f, "unknown piece '{}'", c),
+            FenError::InvalidSideToMove(side) => write!(f, "invalid side to move '{}'", side),
+            FenError::BadKingCount { side, count } => {
+                let side = match side {
+                    Side::White => "white",
+                    Side::Black => "black",
+                    Side::Both => unreachable!(),
+                };
+                write!(f, "{} has {} kings instead of 1", side, count)
+            }
+            FenError::PawnOnBackRank(square) => write!(f, "pawn on back rank square {}", square),
+            FenError::InvalidCastling(castling) => {
+                write!(f, "invalid castling rights '{}'", castling)
+            }
+            FenError::InvalidEnPassant(square) => {
+                write!(f, "invalid en-passant square '{}'", square)
+            }
+            FenError::ImpossibleEnPassant(square) => {
+                write!(f, "impossible en-passant square '{}'", square)
+            }
+            FenError::InvalidHalfmoveClock(clock) => {
+                write!(f, "invalid halfmove clock '{}'", clock)
+            }
+            FenError::InvalidFullmoveNumber(number) => {
+                write!(f, "invalid fullmove number '{}'", number)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+/// Reasons for which a long algebraic notation move can be rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciMoveError {
+    /// The string is not a move in long algebraic notation
+    Invalid(String),
+    /// No legal move matches
+    IllegalMove(String),
+}
+
+impl std::fmt::Display for UciMoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UciMoveError::Invalid(uci) => write!(f, "invalid move '{}'", uci),
+            UciMoveError::IllegalMove(uci) => write!(f, "illegal move '{}'", uci),
+        }
+    }
+}
+
+impl std::error::Error for UciMoveError {}
+
 pub fn opponent_side(side: Side) -> Side {
     match side {
         Side::White => Side::Black,
@@ -74,25 +220,28 @@ pub fn opponent_side(side: Side) -> Side {
     }
 }
 
-#[derive(Clone, Copy)]
-pub struct Board<'a> {
+#[derive(Debug, Clone, Copy)]
+pub struct Board {
     /// One bitboard per piece type, which keeps track of every piece
     /// of this given type.
     /// e.g. white knights starting bitboard is given by B1 | G1.
     /// black rooks starting bitboard is A8 | H8, and so on.
-    pub pieces: [u64; 12],
+    pub pieces: [Bitboard; 12],
 
     /// Occupancies, index by Side enum
     /// 0: White pieces occupancy table
     /// 1: Black pieces occupancy table
     /// 2: All pieces occupancy table
-    pub occupancies: [u64; 3],
+    pub occupancies: [Bitboard; 3],
+
+    /// Piece standing on each square, if any. Redundant with `pieces`,
+    /// and kept in sync with it for direct lookups.
+    pub mailbox: [Option<SidedPiece>; 64],
 
     pub side_to_move: Side,
 
     /// A pawn has just made a two-square move, an en-passant square is then made available.
-    /// Otherwise, this is set to NO_SQUARE(-1)
-    pub en_passant_square: i32,
+    pub en_passant_square: Option<Square>,
 
     /// Bitmask of available castlings (@Castling)
     /// 0001 -> White king-side castling
@@ -101,33 +250,129 @@ pub struct Board<'a> {
     /// 1000 -> Black queen-side castling.
     pub castling_rights: u8,
 
-    /// Attack maps
-    pub attacks: &'a Attacks,
+    /// Initial square of the rook of each castling right, indexed by the bit
+    /// of the right. Only Chess960 positions use other squares than the corners.
+    pub castling_rooks: [Square; 4],
+
+    /// Number of half moves since the last capture or pawn move (fifty-move rule).
+    pub halfmove_clock: u32,
+
+    /// Number of the current move, starting at 1 and incremented after each black move.
+    pub fullmove_number: u32,
+
+    /// Zobrist key of the position, updated incrementally as moves are played.
+    pub hash: u64,
+
+    /// Material and piece-square terms of the evaluation, updated incrementally
+    /// as moves are played.
+    pub evaluation: Evaluation,
 }
 
-impl<'a> Board<'a> {
-    pub fn from_fen(fen: &str, attacks: &'a Attacks) -> Self {
-        let mut pieces = [0u64; 12];
+/// Light squares (a8, h1, and so on), to tell bishops apart by square color.
+const LIGHT_SQUARES: Bitboard = Bitboard(0xAA55_AA55_AA55_AA55);
+
+/// Number of plies without captures or pawn moves after which the game is drawn.
+pub const FIFTY_MOVE_RULE_PLIES: u32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawReason {
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    /// Neither side can possibly checkmate
+    InsufficientMaterial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// The side to move is checkmated
+    Checkmate,
+    Stalemate,
+    Draw(DrawReason),
+}
 
-        let mut fen_iter = fen.split(' ');
-        let position = fen_iter.next().unwrap_or("");
+/// What `Board::make_move` cannot recover from the board after the move,
+/// for `Board::unmake_move` to take it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+    pub mv: Move,
+
+    /// Piece captured on the destination square (en-passant captures excluded).
+    pub captured_piece: Option<Piece>,
+
+    pub castling_rights: u8,
+    pub en_passant_square: Option<Square>,
+    pub halfmove_clock: u32,
+
+    /// Zobrist key of the position before the move.
+    pub hash: u64,
+}
+
+/// Square the rook castles to, next to the king destination:
+/// f1 when the king goes to g1, d1 when it goes to c1 (resp. f8 and d8).
+fn castling_rook_destination(king_dst_square: Square) -> Square {
+    let file = if king_dst_square.file() == 6 { 5 } else { 3 };
+    Square::from_rank_file(king_dst_square.rank(), file)
+}
+
+// Boards are handed over to the search thread, and copied around freely.
+const _: () = {
+    const fn assert_shareable<T: Send + Sync + Copy + 'static>() {}
+    assert_shareable::<Board>();
+};
 
-        let position_iter = position.split('/');
-        for (rank, line) in position_iter.enumerate() {
+impl Board {
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        let mut pieces = [Bitboard::EMPTY; 12];
+        let mut mailbox = [None; 64];
+
+        let mut fen_iter = fen.split_whitespace();
+        let position = fen_iter
+            .next()
+            .ok_or(FenError::MissingField("piece placement"))?;
+
+        let ranks: Vec<&str> = position.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::BadRankCount(ranks.len()));
+        }
+
+        for (rank, line) in ranks.iter().enumerate() {
             let mut file = 0;
             for c in line.chars() {
                 if let Some(piece) = ASCII_TO_PIECE.get(&c) {
-                    let piece_idx = *piece as usize;
-                    pieces[piece_idx] |= bitboard_from_square(get_square(rank as i32, file));
+                    // Do not let the piece spill over the next rank
+                    if file < 8 {
+                        let piece_idx = *piece as usize;
+                        let square = Square::from_rank_file(rank as u8, file as u8);
+                        pieces[piece_idx] |= Bitboard::from_square(square);
+                        mailbox[square.index()] = Some(*piece);
+                    }
                     file += 1;
+                } else if let Some(empty_squares) = c.to_digit(10).filter(|d| (1..=8).contains(d)) {
+                    file += empty_squares as i32;
                 } else {
-                    if c.is_numeric() {
-                        file += (c as u8 - '0' as u8) as i32;
-                    } else {
-                        unreachable!();
-                    }
+                    return Err(FenError::UnknownPiece(c));
                 }
             }
+
+            if file != 8 {
+                return Err(FenError::BadRankLength {
+                    rank: 8 - rank,
+                    length: file,
+                });
+            }
+        }
+
+        // The move generator relies on both of these.
+        for side in [Side::White, Side::Black] {
+            let count = pieces[Piece::King as usize + side as usize * PIECE_COUNT].count();
+            if count != 1 {
+                return Err(FenError::BadKingCount { side, count });
+            }
+        }
+        let pawns = pieces[SidedPiece::WhitePawn as usize] | pieces[SidedPiece::BlackPawn as usize];
+        if let Some(square) = pawns.into_iter().find(|square| square.rank() % 7 == 0) {
+            return Err(FenError::PawnOnBackRank(square));
         }
 
         let occupancies = [
@@ -136,54 +381,389 @@ impl<'a> Board<'a> {
             Self::get_occupancy(&pieces, Side::Both),
         ];
 
-        let side_to_move = match fen_iter.next().unwrap() {
-            "w" => Side::White,
-            "b" => Side::Black,
-            _ => unreachable!(),
+        let side_to_move = match fen_iter.next() {
+            Some("w") => Side::White,
+            Some("b") => Side::Black,
+            Some(other) => return Err(FenError::InvalidSideToMove(other.to_string())),
+            None => return Err(FenError::MissingField("side to move")),
         };
 
-        let castling_str = fen_iter.next().unwrap();
-        let mut castling_rights = 0u8;
-        for c in castling_str.chars() {
-            match c {
-                'K' => castling_rights |= Castling::WhiteKing as u8,
-                'Q' => castling_rights |= Castling::WhiteQueen as u8,
-                'k' => castling_rights |= Castling::BlackKing as u8,
-                'q' => castling_rights |= Castling::BlackQueen as u8,
-                _ => {}
+        let castling_str = fen_iter
+            .next()
+            .ok_or(FenError::MissingField("castling rights"))?;
+        let (castling_rights, castling_rooks) = Self::parse_castling_rights(castling_str, &pieces)?;
+
+        let en_passant_str = fen_iter
+            .next()
+            .ok_or(FenError::MissingField("en-passant square"))?;
+        let en_passant_square = if en_passant_str == "-" {
+            None
+        } else {
+            let square = Square::from_name(en_passant_str)
+                .ok_or_else(|| FenError::InvalidEnPassant(en_passant_str.to_string()))?;
+
+            // The square has to be the one a pawn of the side which just moved
+            // skipped over, with said pawn right in front of it. Both the skipped
+            // square and the one the pawn came from are then empty.
+            let (expected_rank, pawn_offset, pawn_side) = match side_to_move {
+                Side::White => (2, 8, Side::Black),
+                Side::Black => (5, -8, Side::White),
+                Side::Both => unreachable!(),
+            };
+            let pawns = pieces[Piece::Pawn as usize + pawn_side as usize * PIECE_COUNT];
+            let occupancy = occupancies[Side::Both as usize];
+
+            if square.rank() != expected_rank
+                || !pawns.contains(square.offset(pawn_offset))
+                || occupancy.contains(square)
+                || occupancy.contains(square.offset(-pawn_offset))
+            {
+                return Err(FenError::ImpossibleEnPassant(en_passant_str.to_string()));
             }
-        }
 
-        let mut en_passant_square = NO_SQUARE;
-        let en_passant_str = fen_iter.next().unwrap();
-        if let Some(en_passant) = CELL_TO_SQUARE.get(en_passant_str) {
-            en_passant_square = *en_passant;
-        }
+            Some(square)
+        };
 
-        // TODO: Handle 50 moves rule parsing
+        // Move counters are often omitted (e.g. in EPD records),
+        // fall back to the values of a new game in that case.
+        let halfmove_clock = match fen_iter.next() {
+            Some(clock) => clock
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(clock.to_string()))?,
+            None => 0,
+        };
 
-        Self {
+        let fullmove_number = match fen_iter.next() {
+            Some(number) => number
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| FenError::InvalidFullmoveNumber(number.to_string()))?,
+            None => 1,
+        };
+
+        let mut board = Self {
             pieces,
             occupancies,
+            mailbox,
             side_to_move,
             en_passant_square,
             castling_rights,
-            attacks,
+            castling_rooks,
+            halfmove_clock,
+            fullmove_number,
+            hash: 0,
+            evaluation: Evaluation::new(&pieces),
+        };
+        board.hash = board.compute_hash();
+
+        Ok(board)
+    }
+
+    /// Castling rights, along with the rook square of each of them. Besides the
+    /// usual KQkq, which stand for the outermost rook of the wing (X-FEN), rooks
+    /// can be given by their file (Shredder-FEN, e.g. "HAha"), for Chess960.
+    fn parse_castling_rights(
+        castling_str: &str,
+        pieces: &[Bitboard; 12],
+    ) -> Result<(u8, [Square; 4]), FenError> {
+        let mut castling_rights = 0u8;
+        let mut castling_rooks = STANDARD_CASTLING_ROOKS;
+
+        if castling_str == "-" {
+            return Ok((castling_rights, castling_rooks));
+        }
+
+        let invalid = || FenError::InvalidCastling(castling_str.to_string());
+
+        for c in castling_str.chars() {
+            let (side, rank) = if c.is_ascii_uppercase() {
+                (Side::White, 7)
+            } else {
+                (Side::Black, 0)
+            };
+            let on_back_rank = |piece: Piece| {
+                pieces[piece as usize + side as usize * PIECE_COUNT]
+                    .filter(|square| square.rank() == rank)
+                    .map(|square| square.file())
+            };
+            let king_file = on_back_rank(Piece::King).next();
+
+            let (king_side, rook_file) = match c.to_ascii_lowercase() {
+                'k' => {
+                    let outermost = king_file.and_then(|king_file| {
+                        on_back_rank(Piece::Rook).filter(|f| *f > king_file).max()
+                    });
+                    (true, outermost.unwrap_or(7))
+                }
+                'q' => {
+                    let outermost = king_file.and_then(|king_file| {
+                        on_back_rank(Piece::Rook).filter(|f| *f < king_file).min()
+                    });
+                    (false, outermost.unwrap_or(0))
+                }
+                file @ 'a'..='h' => {
+                    let file = file as u8 - b'a';
+                    let king_file = king_file
+                        .filter(|king_file| *king_file != file)
+                        .ok_or_else(invalid)?;
+                    (file > king_file, file)
+                }
+                _ => return Err(invalid()),
+            };
+
+            // Each right may only appear once
+            let right = castling_right(side, king_side);
+            if castling_rights & right != 0 {
+                return Err(invalid());
+            }
+            castling_rights |= right;
+            castling_rooks[right.trailing_zeros() as usize] =
+                Square::from_rank_file(rank, rook_file);
+        }
+
+        Ok((castling_rights, castling_rooks))
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+
+        for rank in 0..8 {
+            let mut empty_squares = 0;
+
+            for file in 0..8 {
+                let square = Square::from_rank_file(rank, file);
+
+                if let Some(piece) = self.piece_at(square) {
+                    if empty_squares > 0 {
+                        fen.push_str(&empty_squares.to_string());
+                        empty_squares = 0;
+                    }
+                    fen.push(PIECE_TO_ASCII[piece as usize]);
+                } else {
+                    empty_squares += 1;
+                }
+            }
+
+            if empty_squares > 0 {
+                fen.push_str(&empty_squares.to_string());
+            }
+            if rank < 7 {
+                fen.push('/');
+            }
         }
+
+        fen.push_str(match self.side_to_move {
+            Side::White => " w ",
+            Side::Black => " b ",
+            Side::Both => unreachable!(),
+        });
+
+        if self.castling_rights == 0 {
+            fen.push('-');
+        } else {
+            for (right, c) in [
+                (Castling::WhiteKing, 'K'),
+                (Castling::WhiteQueen, 'Q'),
+                (Castling::BlackKing, 'k'),
+                (Castling::BlackQueen, 'q'),
+            ] {
+                let right = right as u8;
+                if self.castling_rights & right == 0 {
+                    continue;
+                }
+
+                // KQkq stand for the outermost rooks, others are named by their file.
+                let rook_square = self.castling_rooks[right.trailing_zeros() as usize];
+                let side = if c.is_ascii_uppercase() {
+                    Side::White
+                } else {
+                    Side::Black
+                };
+                let king_side = c.eq_ignore_ascii_case(&'k');
+                let is_outermost = self.bitboard(Piece::Rook, side).all(|rook| {
+                    rook.rank() != rook_square.rank()
+                        || (rook.file() > rook_square.file()) != king_side
+                        || rook == rook_square
+                });
+
+                if is_outermost {
+                    fen.push(c);
+                } else {
+                    let file = (b'a' + rook_square.file()) as char;
+                    fen.push(if side == Side::White {
+                        file.to_ascii_uppercase()
+                    } else {
+                        file
+                    });
+                }
+            }
+        }
+
+        fen.push(' ');
+        match self.en_passant_square {
+            Some(square) => fen.push_str(square.name()),
+            None => fen.push('-'),
+        }
+
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+
+        fen
+    }
+
+    /// Finds the legal move written in long algebraic notation (e.g. e2e4, e7e8q, e1g1),
+    /// with its flags. Castling is written as given by `castling_notation`.
+    pub fn parse_uci_move(&self, uci: &str) -> Result<Move, UciMoveError> {
+        self.parse_uci_move_with(uci, self.castling_notation())
+    }
+
+    /// Notation telling castling moves apart from the other king moves. Only standard
+    /// castling, with the king on the e file and the rooks in the corners, moves the king
+    /// by two squares: otherwise castling may be written like a king step (f1g1 with
+    /// the king on f1 and a rook on h1), so the king takes its rook instead.
+    pub fn castling_notation(&self) -> CastlingNotation {
+        let is_standard = (0..4)
+            .filter(|index| self.castling_rights & (1 << index) != 0)
+            .all(|index| {
+                let side = if index < 2 { Side::White } else { Side::Black };
+                let rook_square = self.castling_rooks[index];
+                rook_square == STANDARD_CASTLING_ROOKS[index]
+                    && self
+                        .bitboard(Piece::King, side)
+                        .any(|king| king == Square::from_rank_file(rook_square.rank(), 4))
+            });
+
+        if is_standard {
+            CastlingNotation::KingToDestination
+        } else {
+            CastlingNotation::KingTakesRook
+        }
+    }
+
+    pub fn parse_uci_move_with(
+        &self,
+        uci: &str,
+        castling_notation: CastlingNotation,
+    ) -> Result<Move, UciMoveError> {
+        let invalid = || UciMoveError::Invalid(uci.to_string());
+
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return Err(invalid());
+        }
+        let src_square = Square::from_name(&uci[0..2]).ok_or_else(invalid)?;
+        let dst_square = Square::from_name(&uci[2..4]).ok_or_else(invalid)?;
+        let promotion_piece = match uci[4..].to_ascii_lowercase().as_str() {
+            "" => None,
+            "n" => Some(Piece::Knight),
+            "b" => Some(Piece::Bishop),
+            "r" => Some(Piece::Rook),
+            "q" => Some(Piece::Queen),
+            _ => return Err(invalid()),
+        };
+
+        generate_moves(self)
+            .moves()
+            .iter()
+            .find(|mv| {
+                let mv_dst_square = match castling_notation {
+                    CastlingNotation::KingTakesRook if mv.is_castling() => {
+                        mv.castling_rook_square()
+                    }
+                    _ => mv.dst_square(),
+                };
+
+                mv.src_square() == src_square
+                    && mv_dst_square == dst_square
+                    && mv.promotion_piece() == promotion_piece
+            })
+            .copied()
+            .ok_or_else(|| UciMoveError::IllegalMove(uci.to_string()))
     }
 
-    pub fn bitboard(&self, piece: Piece, side: Side) -> u64 {
-        let index = piece as usize + side as usize * std::mem::variant_count::<Piece>();
+    /// Status of the game, as far as the board alone can tell: repetitions
+    /// need the previous positions, see `Position::game_status`.
+    pub fn game_status(&self) -> GameStatus {
+        if generate_moves(self).is_empty() {
+            if is_in_check(self, self.side_to_move) {
+                GameStatus::Checkmate
+            } else {
+                GameStatus::Stalemate
+            }
+        } else if self.halfmove_clock >= FIFTY_MOVE_RULE_PLIES {
+            GameStatus::Draw(DrawReason::FiftyMoveRule)
+        } else if self.has_insufficient_material() {
+            GameStatus::Draw(DrawReason::InsufficientMaterial)
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+
+    /// Whether no sequence of legal moves can lead to a checkmate: only kings
+    /// are left, along with either a single minor piece, or bishops all standing
+    /// on squares of the same color.
+    pub fn has_insufficient_material(&self) -> bool {
+        let heavy_pieces_and_pawns =
+            [Piece::Pawn, Piece::Rook, Piece::Queen]
+                .into_iter()
+                .any(|piece| {
+                    !self.bitboard(piece, Side::White).is_empty()
+                        || !self.bitboard(piece, Side::Black).is_empty()
+                });
+        if heavy_pieces_and_pawns {
+            return false;
+        }
+
+        let knights =
+            self.bitboard(Piece::Knight, Side::White) | self.bitboard(Piece::Knight, Side::Black);
+        let bishops =
+            self.bitboard(Piece::Bishop, Side::White) | self.bitboard(Piece::Bishop, Side::Black);
+
+        match (knights.count(), bishops.count()) {
+            (0, 0) | (1, 0) | (0, 1) => true,
+            (0, _) => !bishops.collides(LIGHT_SQUARES) || (bishops & LIGHT_SQUARES) == bishops,
+            _ => false,
+        }
+    }
+
+    /// Computes the Zobrist key of the position from scratch.
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0u64;
+
+        for (piece_index, bitboard) in self.pieces.iter().enumerate() {
+            for square in *bitboard {
+                hash ^= ZOBRIST_KEYS.pieces[piece_index][square.index()];
+            }
+        }
+
+        if let Side::Black = self.side_to_move {
+            hash ^= ZOBRIST_KEYS.side;
+        }
+
+        hash ^= ZOBRIST_KEYS.castling[self.castling_rights as usize];
+
+        if let Some(square) = self.en_passant_square {
+            hash ^= ZOBRIST_KEYS.en_passant_file[square.file() as usize];
+        }
+
+        hash
+    }
+
+    pub fn bitboard(&self, piece: Piece, side: Side) -> Bitboard {
+        let index = piece as usize + side as usize * PIECE_COUNT;
         self.pieces[index]
     }
 
-    pub fn mut_bitboard(&mut self, piece: Piece, side: Side) -> &mut u64 {
-        let index = piece as usize + side as usize * std::mem::variant_count::<Piece>();
+    pub fn mut_bitboard(&mut self, piece: Piece, side: Side) -> &mut Bitboard {
+        let index = piece as usize + side as usize * PIECE_COUNT;
         &mut self.pieces[index]
     }
 
-    pub fn get_occupancy(pieces: &[u64; 12], side: Side) -> u64 {
-        let mut result = 0u64;
+    pub fn get_occupancy(pieces: &[Bitboard; 12], side: Side) -> Bitboard {
+        let mut result = Bitboard::EMPTY;
 
         let indices = match side {
             Side::White => 0..6,
@@ -198,49 +778,597 @@ impl<'a> Board<'a> {
         result
     }
 
-    pub fn play_move(&mut self, mv: i32) -> Self {
-        // TODO: Check the perf of this call.
-        let current_state = self.clone();
+    pub fn piece_at(&self, square: Square) -> Option<SidedPiece> {
+        self.mailbox[square.index()]
+    }
+
+    /// Find which of `side` pieces, if any, stands on the given square.
+    pub fn piece_on(&self, square: Square, side: Side) -> Option<Piece> {
+        self.piece_at(square)
+            .filter(|piece| side == Side::Both || piece.side() == side)
+            .map(SidedPiece::piece)
+    }
+
+    /// Whether the mailbox agrees with the bitboards, for debug assertions.
+    pub fn mailbox_matches_bitboards(&self) -> bool {
+        Square::all().all(|square| {
+            let piece = self
+                .pieces
+                .iter()
+                .position(|bitboard| bitboard.contains(square))
+                .map(|index| SIDED_PIECES[index]);
+
+            self.mailbox[square.index()] == piece
+        })
+    }
+
+    /// Copy-make: plays the move and returns the board as it was before it.
+    pub fn play_move(&mut self, mv: Move) -> Self {
+        let current_state = *self;
+        self.make_move(mv);
+        current_state
+    }
+
+    /// Plays the move, and returns what `unmake_move` needs to take it back.
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        let mut undo = Undo {
+            mv,
+            captured_piece: None,
+            castling_rights: self.castling_rights,
+            en_passant_square: self.en_passant_square,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+
+        let side = self.side_to_move;
+        let opponent = opponent_side(side);
 
         // Reset state
-        self.en_passant_square = NO_SQUARE;
+        if let Some(square) = self.en_passant_square.take() {
+            self.hash ^= ZOBRIST_KEYS.en_passant_file[square.file() as usize];
+        }
 
-        let piece = Move::decode_piece(mv);
+        let piece = mv.piece();
+        let src_square = mv.src_square();
+        let dst_square = mv.dst_square();
 
-        let src_square = Move::decode_src_square(mv);
-        let src_bitbard = bitboard_from_square(src_square);
+        // Captures and pawn moves are irreversible, which resets the fifty-move counter.
+        if matches!(piece, Piece::Pawn) || mv.is_capture() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
 
-        let dst_square = Move::decode_dst_square(mv);
+        if let Side::Black = side {
+            self.fullmove_number += 1;
+        }
+
+        if mv.is_en_passant() {
+            // The captured pawn is not on the destination square, but right behind it.
+            let victim_square = match side {
+                Side::White => dst_square.offset(8),
+                Side::Black => dst_square.offset(-8),
+                Side::Both => unreachable!(),
+            };
+            self.remove_piece(Piece::Pawn, opponent, victim_square);
+        } else if mv.is_capture() {
+            if let Some(victim) = self.piece_at(dst_square) {
+                self.remove_piece(victim.piece(), opponent, dst_square);
+                undo.captured_piece = Some(victim.piece());
+            }
+        }
 
-        let mut bitboard = self.mut_bitboard(piece, self.side_to_move);
-        Board::apply_move_to_bitboard(bitboard, src_square, dst_square);
+        self.remove_piece(piece, side, src_square);
 
-        let mut my_occupancy = &mut self.occupancies[self.side_to_move as usize];
-        Board::apply_move_to_bitboard(my_occupancy, src_square, dst_square);
+        if mv.is_castling() {
+            // In Chess960, the king can land on the rook initial square (or the
+            // other way around), so the rook moves before the king is put down.
+            self.remove_piece(Piece::Rook, side, mv.castling_rook_square());
+            self.add_piece(Piece::Rook, side, castling_rook_destination(dst_square));
+        }
 
-        let mut all_occupancy = &mut self.occupancies[Side::Both as usize];
-        Board::apply_move_to_bitboard(all_occupancy, src_square, dst_square);
+        let promotion_piece = mv.promotion_piece();
+        self.add_piece(promotion_piece.unwrap_or(piece), side, dst_square);
 
-        if Move::is_double_push(mv) {
-            self.en_passant_square = match self.side_to_move {
-                Side::White => dst_square + 8,
-                Side::Black => dst_square - 8,
+        if mv.is_double_push() {
+            let en_passant_square = match side {
+                Side::White => dst_square.offset(8),
+                Side::Black => dst_square.offset(-8),
                 Side::Both => unreachable!(),
             };
+            self.en_passant_square = Some(en_passant_square);
+            self.hash ^= ZOBRIST_KEYS.en_passant_file[en_passant_square.file() as usize];
         }
 
         // Castling rights
-        // Is it a rook move ?
+        // A king or a rook leaving its initial square, or a rook being captured
+        // on its initial square, both revoke the associated castling rights.
+        if self.castling_rights != 0 {
+            self.hash ^= ZOBRIST_KEYS.castling[self.castling_rights as usize];
+
+            if piece == Piece::King {
+                self.castling_rights &= !(castling_right(side, true) | castling_right(side, false));
+            }
+            for (index, rook_square) in self.castling_rooks.iter().enumerate() {
+                if *rook_square == src_square || *rook_square == dst_square {
+                    self.castling_rights &= !(1 << index);
+                }
+            }
 
-        // Is it a king move ?
+            self.hash ^= ZOBRIST_KEYS.castling[self.castling_rights as usize];
+        }
 
-        self.side_to_move = opponent_side(self.side_to_move);
+        self.side_to_move = opponent;
+        self.hash ^= ZOBRIST_KEYS.side;
 
-        current_state
+        undo
+    }
+
+    /// Takes back the last move played by `make_move`.
+    pub fn unmake_move(&mut self, undo: Undo) {
+        let mv = undo.mv;
+        let side = opponent_side(self.side_to_move);
+        let opponent = self.side_to_move;
+
+        let piece = mv.piece();
+        let src_square = mv.src_square();
+        let dst_square = mv.dst_square();
+
+        // Same as in `make_move`, the king and the rook may swap their squares.
+        self.remove_piece(mv.promotion_piece().unwrap_or(piece), side, dst_square);
+
+        if mv.is_castling() {
+            self.remove_piece(Piece::Rook, side, castling_rook_destination(dst_square));
+            self.add_piece(Piece::Rook, side, mv.castling_rook_square());
+        }
+
+        self.add_piece(piece, side, src_square);
+
+        if mv.is_en_passant() {
+            let victim_square = match side {
+                Side::White => dst_square.offset(8),
+                Side::Black => dst_square.offset(-8),
+                Side::Both => unreachable!(),
+            };
+            self.add_piece(Piece::Pawn, opponent, victim_square);
+        } else if let Some(victim) = undo.captured_piece {
+            self.add_piece(victim, opponent, dst_square);
+        }
+
+        if let Side::Black = side {
+            self.fullmove_number -= 1;
+        }
+
+        self.side_to_move = side;
+        self.castling_rights = undo.castling_rights;
+        self.en_passant_square = undo.en_passant_square;
+        self.halfmove_clock = undo.halfmove_clock;
+
+        // Moving the pieces back has updated the hash, but not for the other
+        // state changes: restoring it is simpler.
+        self.hash = undo.hash;
+    }
+
+    fn add_piece(&mut self, piece: Piece, side: Side, square: Square) {
+        let square_bitboard = Bitboard::from_square(square);
+
+        let piece_index = piece as usize + side as usize * PIECE_COUNT;
+        self.hash ^= ZOBRIST_KEYS.pieces[piece_index][square.index()];
+        self.evaluation.add_piece(piece, side, square);
+
+        *self.mut_bitboard(piece, side) |= square_bitboard;
+        self.mailbox[square.index()] = Some(SidedPiece::new(piece, side));
+        self.occupancies[side as usize] |= square_bitboard;
+        self.occupancies[Side::Both as usize] |= square_bitboard;
+    }
+
+    fn remove_piece(&mut self, piece: Piece, side: Side, square: Square) {
+        let square_bitboard = Bitboard::from_square(square);
+
+        let piece_index = piece as usize + side as usize * PIECE_COUNT;
+        self.hash ^= ZOBRIST_KEYS.pieces[piece_index][square.index()];
+        self.evaluation.remove_piece(piece, side, square);
+
+        *self.mut_bitboard(piece, side) &= !square_bitboard;
+        self.mailbox[square.index()] = None;
+        self.occupancies[side as usize] &= !square_bitboard;
+        self.occupancies[Side::Both as usize] &= !square_bitboard;
+    }
+}
+
+/// A board along with the moves which led to it, which can be taken back one by one.
+/// The keys of the previous positions are kept for repetition detection.
+#[derive(Debug, Clone)]
+pub struct Position {
+    board: Board,
+    history: Vec<Undo>,
+}
+
+impl Position {
+    pub fn new(board: Board) -> Self {
+        Self {
+            board,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn make_move(&mut self, mv: Move) {
+        let undo = self.board.make_move(mv);
+        self.history.push(undo);
+    }
+
+    /// Takes back the last move, if any, and returns it.
+    pub fn unmake_move(&mut self) -> Option<Move> {
+        let undo = self.history.pop()?;
+        self.board.unmake_move(undo);
+        Some(undo.mv)
+    }
+
+    /// Moves played since the position was created, with what is needed to take them back.
+    pub fn history(&self) -> &[Undo] {
+        &self.history
+    }
+
+    /// Zobrist keys of the previous positions, the most recent one first.
+    pub fn previous_hashes(&self) -> impl Iterator<Item = u64> + '_ {
+        self.history.iter().rev().map(|undo| undo.hash)
+    }
+
+    /// How many times the current position occurred before. Only the positions
+    /// since the last capture or pawn move can be the same, with the same side to move.
+    pub fn repetitions(&self) -> usize {
+        self.previous_hashes()
+            .take(self.board.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|hash| *hash == self.board.hash)
+            .count()
+    }
+
+    /// Same as `Board::game_status`, with threefold repetitions detected.
+    pub fn game_status(&self) -> GameStatus {
+        match self.board.game_status() {
+            GameStatus::Ongoing if self.repetitions() >= 2 => {
+                GameStatus::Draw(DrawReason::ThreefoldRepetition)
+            }
+            status => status,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fens;
+
+    #[test]
+    fn invalid_fens_are_rejected() {
+        let cases = [
+            ("", FenError::MissingField("piece placement")),
+            ("4k3/8/8/8/8/8/4K3", FenError::BadRankCount(7)),
+            (
+                "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+                FenError::BadRankLength { rank: 1, length: 9 },
+            ),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", FenError::UnknownPiece('X')),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+                FenError::InvalidSideToMove("x".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+                FenError::BadKingCount {
+                    side: Side::White,
+                    count: 2,
+                },
+            ),
+            (
+                "8/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::BadKingCount {
+                    side: Side::Black,
+                    count: 0,
+                },
+            ),
+            (
+                "P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::PawnOnBackRank(a8),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2p b - - 0 1",
+                FenError::PawnOnBackRank(h1),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2R w KK - 0 1",
+                FenError::InvalidCastling("KK".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e9 0 1",
+                FenError::InvalidEnPassant("e9".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e6 0 1",
+                FenError::ImpossibleEnPassant("e6".to_string()),
+            ),
+            (
+                "4k3/8/3N4/3pP3/8/8/8/4K3 w - d6 0 1",
+                FenError::ImpossibleEnPassant("d6".to_string()),
+            ),
+            (
+                "4k3/3n4/8/3pP3/8/8/8/4K3 w - d6 0 1",
+                FenError::ImpossibleEnPassant("d6".to_string()),
+            ),
+            (
+                "4k3/8/8/8/4pP2/8/5B2/4K3 b - f3 0 1",
+                FenError::ImpossibleEnPassant("f3".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+                FenError::InvalidHalfmoveClock("x".to_string()),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+                FenError::InvalidFullmoveNumber("0".to_string()),
+            ),
+        ];
+
+        for (fen, error) in cases {
+            assert_eq!(Board::from_fen(fen).err(), Some(error), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn uci_moves_are_found_with_their_flags() {
+        let board = Board::from_fen(fens::STARTING_BOARD_FEN).unwrap();
+        assert!(board.parse_uci_move("e2e4").unwrap().is_double_push());
+
+        let board = Board::from_fen("r3k3/1P6/8/3pP3/8/8/8/4K2R w K d6 0 1").unwrap();
+        assert!(board.parse_uci_move("e5d6").unwrap().is_en_passant());
+        assert!(board.parse_uci_move("e1g1").unwrap().is_castling());
+        assert_eq!(
+            board.parse_uci_move("b7a8n").unwrap().promotion_piece(),
+            Some(Piece::Knight)
+        );
+
+        assert_eq!(
+            board.parse_uci_move("b7b8"),
+            Err(UciMoveError::IllegalMove("b7b8".to_string()))
+        );
+        assert_eq!(
+            board.parse_uci_move("e1h1"),
+            Err(UciMoveError::IllegalMove("e1h1".to_string()))
+        );
+        for invalid in ["", "e2", "e2e9", "e7e8k", "e7e8qq"] {
+            assert_eq!(
+                board.parse_uci_move(invalid),
+                Err(UciMoveError::Invalid(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn castling_can_be_written_king_takes_rook() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1").unwrap();
+
+        for (uci, king_takes_rook) in [("e8g8", "e8h8"), ("e8c8", "e8a8")] {
+            let mv = board
+                .parse_uci_move_with(king_takes_rook, CastlingNotation::KingTakesRook)
+                .unwrap();
+
+            assert!(mv.is_castling());
+            assert_eq!(mv.to_uci(), uci);
+            assert_eq!(
+                mv.to_uci_with(CastlingNotation::KingTakesRook),
+                king_takes_rook
+            );
+        }
+    }
+
+    #[test]
+    fn shredder_fen_castling_rights_name_the_rook_files() {
+        // Outermost rooks are written KQkq, inner ones by their file.
+        let board = Board::from_fen("rk5r/8/8/8/8/8/8/RK3R1R w AFha - 0 1").unwrap();
+        assert_eq!(board.to_fen(), "rk5r/8/8/8/8/8/8/RK3R1R w FQkq - 0 1");
+        assert_eq!(
+            Board::from_fen(&board.to_fen()).unwrap().castling_rooks,
+            board.castling_rooks
+        );
+
+        assert_eq!(
+            fens::chess960_fen(518).as_deref(),
+            Some(fens::STARTING_BOARD_FEN)
+        );
+        assert_eq!(fens::chess960_fen(fens::CHESS960_POSITION_COUNT), None);
+
+        for index in 0..fens::CHESS960_POSITION_COUNT {
+            let fen = fens::chess960_fen(index).unwrap();
+            assert_eq!(Board::from_fen(&fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn chess960_castling_places_king_and_rook() {
+        // The king stays on g1, only the rook moves.
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/6KR w H - 0 1").unwrap();
+        let mv = board
+            .parse_uci_move_with("g1h1", CastlingNotation::KingTakesRook)
+            .unwrap();
+        assert_eq!(mv.to_uci(), "g1g1");
+        board.play_move(mv);
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+
+        // The king moves towards the h file to castle queen side.
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/RK6 w A - 0 1").unwrap();
+        board.play_move(board.parse_uci_move("b1a1").unwrap());
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+
+        // The rook hides an attack on the king's destination.
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/qRK5 w B - 0 1").unwrap();
+        assert!(generate_moves(&board)
+            .moves()
+            .iter()
+            .all(|mv| !mv.is_castling()));
     }
 
-    fn apply_move_to_bitboard(bitboard: &mut u64, src_square: i32, dst_square: i32) {
-        *bitboard = pop_bit(*bitboard, src_square);
-        *bitboard |= bitboard_from_square(dst_square);
+    #[test]
+    fn chess960_castling_is_not_confused_with_a_king_step() {
+        // Castling and the king step to g1 would both be written f1g1.
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/5K1R w H - 0 1").unwrap();
+        assert_eq!(board.castling_notation(), CastlingNotation::KingTakesRook);
+
+        let step = board.parse_uci_move("f1g1").unwrap();
+        assert!(!step.is_castling());
+        assert_eq!(step.dst_square(), g1);
+        assert!(board.parse_uci_move("f1h1").unwrap().is_castling());
+
+        let moves = generate_moves(&board);
+        let mut names: Vec<String> = moves
+            .moves()
+            .iter()
+            .map(|mv| mv.to_uci_with(board.castling_notation()))
+            .collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), moves.len());
+
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(
+            board.castling_notation(),
+            CastlingNotation::KingToDestination
+        );
+        assert!(board.parse_uci_move("e1g1").unwrap().is_castling());
+    }
+
+    #[test]
+    fn unmake_move_restores_the_board() {
+        let fens = [
+            fens::STARTING_BOARD_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/1P6/8/3pP3/8/8/8/4K2R w Kkq d6 3 20",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4k3/8/8/8/2pP4/8/8/4K3 b - d3 0 40",
+            "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1",
+            "rk5r/8/8/8/8/8/8/RK3R1R w FQkq - 0 1",
+        ];
+
+        for fen in fens {
+            let mut position = Position::new(Board::from_fen(fen).unwrap());
+
+            for mv in generate_moves(position.board()).moves() {
+                let before = *position.board();
+                position.make_move(*mv);
+                assert_eq!(position.previous_hashes().next(), Some(before.hash));
+                assert_eq!(position.unmake_move(), Some(*mv));
+
+                let after = position.board();
+                assert_eq!(after.to_fen(), fen, "after {}", mv);
+                assert_eq!(after.hash, before.hash);
+                assert_eq!(after.evaluation, before.evaluation);
+                assert_eq!(after.occupancies, before.occupancies);
+                assert_eq!(after.mailbox, before.mailbox);
+            }
+
+            assert_eq!(position.unmake_move(), None);
+        }
+    }
+
+    #[test]
+    fn mailbox_follows_the_moves() {
+        let mut board = Board::from_fen("r3k3/1P6/8/3pP3/8/8/8/4K2R w K d6 0 1").unwrap();
+        assert_eq!(
+            board.piece_at(Square::from_name("a8").unwrap()),
+            Some(SidedPiece::BlackRook)
+        );
+        assert_eq!(board.piece_at(Square::from_name("a1").unwrap()), None);
+
+        for uci in ["e5d6", "e8d8", "b7a8q", "d8d7", "e1g1"] {
+            let mv = board.parse_uci_move(uci).unwrap();
+            board.play_move(mv);
+            assert!(board.mailbox_matches_bitboards(), "after {}", uci);
+        }
+
+        let piece_at = |name| board.piece_at(Square::from_name(name).unwrap());
+        assert_eq!(piece_at("d5"), None);
+        assert_eq!(piece_at("d6"), Some(SidedPiece::WhitePawn));
+        assert_eq!(piece_at("a8"), Some(SidedPiece::WhiteQueen));
+        assert_eq!(piece_at("f1"), Some(SidedPiece::WhiteRook));
+        assert_eq!(piece_at("g1").map(SidedPiece::side), Some(Side::White));
+    }
+
+    #[test]
+    fn game_status_applies_the_draw_rules() {
+        let status = |fen| Board::from_fen(fen).unwrap().game_status();
+
+        assert_eq!(status(fens::STARTING_BOARD_FEN), GameStatus::Ongoing);
+        assert_eq!(
+            status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"),
+            GameStatus::Checkmate
+        );
+        assert_eq!(
+            status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"),
+            GameStatus::Stalemate
+        );
+
+        let fifty_moves = GameStatus::Draw(DrawReason::FiftyMoveRule);
+        assert_eq!(
+            status("4k3/8/8/8/8/8/4P3/4K3 w - - 99 80"),
+            GameStatus::Ongoing
+        );
+        assert_eq!(status("4k3/8/8/8/8/8/4P3/4K3 w - - 100 80"), fifty_moves);
+        // Checkmate takes precedence over the fifty-move rule.
+        assert_eq!(
+            status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80"),
+            GameStatus::Checkmate
+        );
+
+        let insufficient = GameStatus::Draw(DrawReason::InsufficientMaterial);
+        for fen in [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K3 b - - 0 1",
+            "2b1k3/8/8/8/8/8/8/3BK3 w - - 0 1",
+            "4kb2/8/8/8/8/8/1B6/2B1K3 w - - 0 1",
+        ] {
+            assert_eq!(status(fen), insufficient, "{}", fen);
+        }
+        for fen in [
+            "4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1",
+            "3bk3/8/8/8/8/8/8/3BK3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/NN2K3 w - - 0 1",
+            "4k3/p7/8/8/8/8/8/4K3 w - - 0 1",
+        ] {
+            assert_eq!(status(fen), GameStatus::Ongoing, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn repetitions_are_counted_since_the_last_irreversible_move() {
+        let mut position = Position::new(Board::from_fen(fens::STARTING_BOARD_FEN).unwrap());
+        let play = |position: &mut Position, moves: &[&str]| {
+            for uci in moves {
+                let mv = position.board().parse_uci_move(uci).unwrap();
+                position.make_move(mv);
+            }
+        };
+
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        play(&mut position, &shuffle);
+        assert_eq!(position.repetitions(), 1);
+        assert_eq!(position.game_status(), GameStatus::Ongoing);
+
+        play(&mut position, &shuffle);
+        assert_eq!(position.repetitions(), 2);
+        assert_eq!(
+            position.game_status(),
+            GameStatus::Draw(DrawReason::ThreefoldRepetition)
+        );
+
+        // A pawn move makes the previous positions unreachable.
+        play(&mut position, &["e2e3", "e7e6"]);
+        play(&mut position, &shuffle);
+        assert_eq!(position.repetitions(), 1);
     }
 }
@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-use crate::bitboard::*;
+//! Generation of the constant tables of the engine. Besides being part of the crate,
+//! this module is included by the build script, so it only depends on `rand`.
+
+use std::io::{self, Write};
+
+use crate::rand::next_magic_candidate;
 
 pub const fn get_square(rank: i32, file: i32) -> i32 {
     rank * 8 + file
@@ -20,8 +25,13 @@ where
 
 pub fn generate_squares() {
     for_each_square(&mut |file, rank, square| {
-        let letter: char = ('A' as u8 + file as u8).into();
-        println!("pub const {}{}: i32 = {};", letter, 8 - rank, square);
+        let letter: char = (b'A' + file as u8).into();
+        println!(
+            "pub const {}{}: Square = Square({});",
+            letter,
+            8 - rank,
+            square
+        );
     });
 }
 
@@ -31,7 +41,7 @@ pub const fn not_a_file() -> u64 {
     while rank < 8 {
         let mut file = 1;
         while file < 8 {
-            bitboard |= bitboard_from_square(get_square(rank, file));
+            bitboard |= 1 << get_square(rank, file);
             file += 1;
         }
         rank += 1;
@@ -46,7 +56,7 @@ pub const fn not_h_file() -> u64 {
     while rank < 8 {
         let mut file = 0;
         while file < 7 {
-            bitboard |= bitboard_from_square(get_square(rank, file));
+            bitboard |= 1 << get_square(rank, file);
             file += 1;
         }
         rank += 1;
@@ -61,7 +71,7 @@ pub const fn not_ab_file() -> u64 {
     while rank < 8 {
         let mut file = 2;
         while file < 8 {
-            bitboard |= bitboard_from_square(get_square(rank, file));
+            bitboard |= 1 << get_square(rank, file);
             file += 1;
         }
         rank += 1;
@@ -76,7 +86,7 @@ pub const fn not_gh_file() -> u64 {
     while rank < 8 {
         let mut file = 0;
         while file < 6 {
-            bitboard |= bitboard_from_square(get_square(rank, file));
+            bitboard |= 1 << get_square(rank, file);
             file += 1;
         }
         rank += 1;
@@ -84,3 +94,328 @@ pub const fn not_gh_file() -> u64 {
 
     bitboard
 }
+
+/// Seed of the magic numbers search, kept fixed so that builds are reproducible.
+const MAGIC_SEED: u32 = 1804289383;
+
+pub fn mask_pawn_attacks(square: i32, white: bool) -> u64 {
+    let bitboard = 1u64 << square;
+
+    if white {
+        ((bitboard >> 9) & not_h_file()) | ((bitboard >> 7) & not_a_file())
+    } else {
+        ((bitboard << 7) & not_h_file()) | ((bitboard << 9) & not_a_file())
+    }
+}
+
+pub fn mask_knight_attacks(square: i32) -> u64 {
+    let bitboard = 1u64 << square;
+
+    ((bitboard >> 15) & not_a_file())
+        | ((bitboard >> 17) & not_h_file())
+        | ((bitboard >> 6) & not_ab_file())
+        | ((bitboard >> 10) & not_gh_file())
+        | ((bitboard << 17) & not_a_file())
+        | ((bitboard << 15) & not_h_file())
+        | ((bitboard << 10) & not_ab_file())
+        | ((bitboard << 6) & not_gh_file())
+}
+
+pub fn mask_king_attacks(square: i32) -> u64 {
+    let bitboard = 1u64 << square;
+
+    (bitboard >> 8)
+        | (bitboard << 8)
+        | ((bitboard << 1) & not_a_file())
+        | ((bitboard >> 1) & not_h_file())
+        | ((bitboard >> 7) & not_a_file())
+        | ((bitboard >> 9) & not_h_file())
+        | ((bitboard << 9) & not_a_file())
+        | ((bitboard << 7) & not_h_file())
+}
+
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (0, -1), (1, 0), (0, 1)];
+
+/// Squares reached from `square` by sliding along `directions` until a blocker
+/// (included) or the edge of the board.
+fn slide(square: i32, directions: &[(i32, i32); 4], blocker: u64) -> u64 {
+    let mut attacks = 0u64;
+
+    for (rank_step, file_step) in directions {
+        let mut rank = square / 8 + rank_step;
+        let mut file = square % 8 + file_step;
+
+        while (0..8).contains(&rank) && (0..8).contains(&file) {
+            let bitboard = 1u64 << get_square(rank, file);
+            attacks |= bitboard;
+            if blocker & bitboard != 0 {
+                break;
+            }
+            rank += rank_step;
+            file += file_step;
+        }
+    }
+
+    attacks
+}
+
+/// Relevant occupancy mask: the attacked squares on an empty board, minus the
+/// last square of each ray, which cannot hide anything behind it.
+fn relevant_mask(square: i32, directions: &[(i32, i32); 4]) -> u64 {
+    let on_board = |rank: i32, file: i32| (0..8).contains(&rank) && (0..8).contains(&file);
+    let mut mask = 0u64;
+
+    for (rank_step, file_step) in directions {
+        let mut rank = square / 8 + rank_step;
+        let mut file = square % 8 + file_step;
+
+        while on_board(rank, file) && on_board(rank + rank_step, file + file_step) {
+            mask |= 1u64 << get_square(rank, file);
+            rank += rank_step;
+            file += file_step;
+        }
+    }
+
+    mask
+}
+
+pub fn mask_bishop_attacks(square: i32) -> u64 {
+    relevant_mask(square, &BISHOP_DIRECTIONS)
+}
+
+pub fn mask_rook_attacks(square: i32) -> u64 {
+    relevant_mask(square, &ROOK_DIRECTIONS)
+}
+
+pub fn bishop_attacks_on_the_fly(square: i32, blocker: u64) -> u64 {
+    slide(square, &BISHOP_DIRECTIONS, blocker)
+}
+
+pub fn rook_attacks_on_the_fly(square: i32, blocker: u64) -> u64 {
+    slide(square, &ROOK_DIRECTIONS, blocker)
+}
+
+/// The `index`-th subset of the attack mask squares.
+pub fn set_occupancy(index: usize, mut attack_mask: u64) -> u64 {
+    let mut occupancy = 0u64;
+    let mut count = 0;
+
+    while attack_mask != 0 {
+        let bit = attack_mask & attack_mask.wrapping_neg();
+        if index & (1 << count) != 0 {
+            occupancy |= bit;
+        }
+        attack_mask ^= bit;
+        count += 1;
+    }
+
+    occupancy
+}
+
+/// Index of an occupancy in the attack table of a square.
+pub const fn magic_index(occupancy: u64, mask: u64, magic: u64, relevant_bits: u32) -> usize {
+    ((occupancy & mask).wrapping_mul(magic) >> (64 - relevant_bits)) as usize
+}
+
+/// Description of the slider attacks of one piece type, one entry per square.
+pub struct SliderTables {
+    pub masks: [u64; 64],
+    pub relevant_bits: [u32; 64],
+    pub magics: [u64; 64],
+
+    /// `1 << relevant_bits` entries per square, indexed by `magic_index`.
+    pub attacks: Vec<Vec<u64>>,
+
+    pub attacks_on_the_fly: fn(i32, u64) -> u64,
+}
+
+impl SliderTables {
+    pub fn bishop(random_state: &mut u32) -> Self {
+        Self::new(random_state, mask_bishop_attacks, bishop_attacks_on_the_fly)
+    }
+
+    pub fn rook(random_state: &mut u32) -> Self {
+        Self::new(random_state, mask_rook_attacks, rook_attacks_on_the_fly)
+    }
+
+    fn new(
+        random_state: &mut u32,
+        mask: fn(i32) -> u64,
+        attacks_on_the_fly: fn(i32, u64) -> u64,
+    ) -> Self {
+        let mut tables = Self {
+            masks: [0; 64],
+            relevant_bits: [0; 64],
+            magics: [0; 64],
+            attacks: Vec::new(),
+            attacks_on_the_fly,
+        };
+
+        for square in 0..64 {
+            let mask = mask(square);
+            let relevant_bits = mask.count_ones();
+            let (magic, attacks) =
+                find_magic_number(random_state, square, mask, attacks_on_the_fly);
+
+            tables.masks[square as usize] = mask;
+            tables.relevant_bits[square as usize] = relevant_bits;
+            tables.magics[square as usize] = magic;
+            tables.attacks.push(attacks);
+        }
+
+        tables
+    }
+}
+
+/// Tries random sparse numbers until one maps every occupancy of the mask to an
+/// index holding its attacks, without destructive collisions. Returns the magic
+/// along with the resulting attack table.
+fn find_magic_number(
+    random_state: &mut u32,
+    square: i32,
+    mask: u64,
+    attacks_on_the_fly: fn(i32, u64) -> u64,
+) -> (u64, Vec<u64>) {
+    let relevant_bits = mask.count_ones();
+    let occupancy_indices = 1 << relevant_bits;
+
+    let occupancies: Vec<u64> = (0..occupancy_indices)
+        .map(|index| set_occupancy(index, mask))
+        .collect();
+    let attacks: Vec<u64> = occupancies
+        .iter()
+        .map(|occupancy| attacks_on_the_fly(square, *occupancy))
+        .collect();
+
+    loop {
+        let (magic, rnd) = next_magic_candidate(*random_state);
+        *random_state = rnd;
+
+        // Ignore inappropriate magics
+        if (mask.wrapping_mul(magic) & 0xFF00000000000000).count_ones() < 6 {
+            continue;
+        }
+
+        let mut used_attacks = vec![0u64; occupancy_indices];
+        let found = occupancies.iter().zip(&attacks).all(|(occupancy, attack)| {
+            let used = &mut used_attacks[magic_index(*occupancy, mask, magic, relevant_bits)];
+            if *used == 0 {
+                *used = *attack;
+            }
+            *used == *attack
+        });
+
+        if found {
+            return (magic, used_attacks);
+        }
+    }
+}
+
+/// For each pair of squares sharing a rank, a file or a diagonal, computes
+/// the squares strictly between them, and the whole line going through both
+/// of them, edge to edge. Both are empty for unaligned squares.
+pub fn between_and_lines() -> (Vec<u64>, Vec<u64>) {
+    let mut between = vec![0u64; 64 * 64];
+    let mut line = vec![0u64; 64 * 64];
+
+    for square_a in 0..64 {
+        for square_b in 0..64 {
+            if square_a == square_b {
+                continue;
+            }
+
+            let bitboard_a = 1u64 << square_a;
+            let bitboard_b = 1u64 << square_b;
+            let index = (square_a * 64 + square_b) as usize;
+
+            for attacks_on_the_fly in [rook_attacks_on_the_fly, bishop_attacks_on_the_fly] {
+                if attacks_on_the_fly(square_a, 0) & bitboard_b != 0 {
+                    between[index] = attacks_on_the_fly(square_a, bitboard_b)
+                        & attacks_on_the_fly(square_b, bitboard_a);
+                    line[index] = attacks_on_the_fly(square_a, 0) & attacks_on_the_fly(square_b, 0)
+                        | bitboard_a
+                        | bitboard_b;
+                }
+            }
+        }
+    }
+
+    (between, line)
+}
+
+fn write_table(out: &mut impl Write, name: &str, ty: &str, values: &[u64]) -> io::Result<()> {
+    writeln!(out, "static {}: [{}; {}] = [", name, ty, values.len())?;
+    for chunk in values.chunks(8) {
+        let row: Vec<String> = chunk.iter().map(|value| format!("{:#x}", value)).collect();
+        writeln!(out, "    {},", row.join(", "))?;
+    }
+    writeln!(out, "];")
+}
+
+/// Writes the Rust source of every attack table, along with the magic numbers used
+/// to index the slider ones, and their packed PEXT variants. Called from the build script.
+pub fn write_attack_tables(out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "// Generated by build.rs, do not edit.")?;
+
+    let white_pawn: Vec<u64> = (0..64)
+        .map(|square| mask_pawn_attacks(square, true))
+        .collect();
+    let black_pawn: Vec<u64> = (0..64)
+        .map(|square| mask_pawn_attacks(square, false))
+        .collect();
+    let knight: Vec<u64> = (0..64).map(mask_knight_attacks).collect();
+    let king: Vec<u64> = (0..64).map(mask_king_attacks).collect();
+    write_table(out, "WHITE_PAWN_ATTACKS", "u64", &white_pawn)?;
+    write_table(out, "BLACK_PAWN_ATTACKS", "u64", &black_pawn)?;
+    write_table(out, "KNIGHT_ATTACKS", "u64", &knight)?;
+    write_table(out, "KING_ATTACKS", "u64", &king)?;
+
+    let (between, line) = between_and_lines();
+    write_table(out, "BETWEEN", "u64", &between)?;
+    write_table(out, "LINE", "u64", &line)?;
+
+    let mut random_state = MAGIC_SEED;
+    let bishop = SliderTables::bishop(&mut random_state);
+    let rook = SliderTables::rook(&mut random_state);
+
+    // "Fancy" magic layout: the tables of all the squares, bishops then rooks, are packed
+    // into a single one, each square taking `1 << relevant_bits` entries from its offset.
+    // PEXT indexing uses the same layout, as `pext(set_occupancy(index, mask), mask)`
+    // is `index` by construction.
+    let mut attacks = Vec::new();
+    let mut pext_attacks = Vec::new();
+
+    for (prefix, tables) in [("BISHOP", &bishop), ("ROOK", &rook)] {
+        let relevant_bits: Vec<u64> = tables
+            .relevant_bits
+            .iter()
+            .map(|bits| *bits as u64)
+            .collect();
+        write_table(out, &format!("{}_MASKS", prefix), "u64", &tables.masks)?;
+        write_table(out, &format!("{}_MAGICS", prefix), "u64", &tables.magics)?;
+        write_table(
+            out,
+            &format!("{}_RELEVANT_BITS", prefix),
+            "u32",
+            &relevant_bits,
+        )?;
+
+        let mut offsets = Vec::with_capacity(64);
+        for (square, square_attacks) in tables.attacks.iter().enumerate() {
+            offsets.push(attacks.len() as u64);
+            attacks.extend_from_slice(square_attacks);
+
+            let mask = tables.masks[square];
+            pext_attacks.extend((0..square_attacks.len()).map(|index| {
+                (tables.attacks_on_the_fly)(square as i32, set_occupancy(index, mask))
+            }));
+        }
+        write_table(out, &format!("{}_OFFSETS", prefix), "u32", &offsets)?;
+    }
+
+    write_table(out, "SLIDER_ATTACKS", "u64", &attacks)?;
+    write_table(out, "SLIDER_PEXT_ATTACKS", "u64", &pext_attacks)?;
+
+    Ok(())
+}
@@ -0,0 +1,526 @@
+//! Extended Position Description: a position followed by operations, such as
+//! the best moves of a test suite position ("bm Qxf7+; id \"WAC.001\";").
+
+use std::sync::atomic::AtomicBool;
+
+use crate::board::*;
+use crate::move_generator::Move;
+use crate::san::{move_to_san, parse_san, SanError, SanMode};
+use crate::search::{Search, SearchLimits, MATE_SCORE, MATE_THRESHOLD};
+use crate::tt::{TranspositionTable, DEFAULT_HASH_SIZE_MB};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// "bm": the best moves of the position
+    BestMoves(Vec<Move>),
+    /// "am": moves to avoid
+    AvoidMoves(Vec<Move>),
+    /// "id": name of the position
+    Id(String),
+    /// "c0" to "c9": comments
+    Comment(u8, String),
+    /// "dm": the side to move mates in this many moves
+    DirectMate(u32),
+    /// "pv": predicted variation
+    PredictedVariation(Vec<Move>),
+    /// "acd": depth the position has been analysed to
+    AnalysisDepth(u32),
+    /// "ce": evaluation of the position, in centipawns from the side to move point of view
+    CentipawnEvaluation(i32),
+    /// Any other opcode, with its raw operands
+    Other {
+        opcode: String,
+        operands: Vec<String>,
+    },
+}
+
+/// Reasons for which an EPD record can be rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    InvalidPosition(FenError),
+    /// A quoted operand is not closed
+    UnterminatedString,
+    /// An operand is not valid for its opcode
+    InvalidOperand {
+        opcode: String,
+        operand: String,
+    },
+    /// A move operand is not legal in the position
+    InvalidMove {
+        opcode: String,
+        error: SanError,
+    },
+}
+
+impl std::fmt::Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpdError::InvalidPosition(error) => write!(f, "invalid position: {}", error),
+            EpdError::UnterminatedString => write!(f, "unterminated string operand"),
+            EpdError::InvalidOperand { opcode, operand } => {
+                write!(f, "invalid operand '{}' for '{}'", operand, opcode)
+            }
+            EpdError::InvalidMove { opcode, error } => write!(f, "{} in '{}'", error, opcode),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+#[derive(Debug, Clone)]
+pub struct Epd {
+    pub board: Board,
+
+    /// Operations, in their original order.
+    pub operations: Vec<Operation>,
+}
+
+impl Epd {
+    pub fn new(board: Board) -> Self {
+        Self {
+            board,
+            operations: Vec::new(),
+        }
+    }
+
+    /// Parses a record: the four first FEN fields, then semicolon terminated
+    /// operations. The move counters are taken from the "hmvc" and "fmvn" opcodes.
+    pub fn from_epd(epd: &str) -> Result<Self, EpdError> {
+        let epd = epd.trim();
+        let mut fields_end = 0;
+        for _ in 0..4 {
+            let rest = &epd[fields_end..];
+            let start = rest.len() - rest.trim_start().len();
+            let field_length = rest[start..]
+                .find(char::is_whitespace)
+                .unwrap_or(rest.len() - start);
+            fields_end += start + field_length;
+        }
+
+        let board = Board::from_fen(&epd[..fields_end]).map_err(EpdError::InvalidPosition)?;
+        let mut record = Self::new(board);
+
+        for (opcode, operands) in split_operations(&epd[fields_end..])? {
+            let operation = record.parse_operation(opcode, operands)?;
+
+            if let Operation::Other { opcode, operands } = &operation {
+                let counter = operands.first().and_then(|operand| operand.parse().ok());
+                match (opcode.as_str(), counter) {
+                    ("hmvc", Some(clock)) => record.board.halfmove_clock = clock,
+                    ("fmvn", Some(number)) if number > 0 => record.board.fullmove_number = number,
+                    ("hmvc" | "fmvn", _) => {
+                        return Err(EpdError::InvalidOperand {
+                            opcode: opcode.clone(),
+                            operand: operands.join(" "),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+
+            record.operations.push(operation);
+        }
+
+        Ok(record)
+    }
+
+    fn parse_operation(
+        &self,
+        opcode: String,
+        operands: Vec<String>,
+    ) -> Result<Operation, EpdError> {
+        let invalid_operand = || EpdError::InvalidOperand {
+            opcode: opcode.clone(),
+            operand: operands.join(" "),
+        };
+        let single_operand = || match operands.as_slice() {
+            [operand] => Ok(operand.clone()),
+            _ => Err(invalid_operand()),
+        };
+
+        let operation = match opcode.as_str() {
+            "bm" | "am" => {
+                let moves = operands
+                    .iter()
+                    .map(|san| parse_move(&opcode, &self.board, san))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if opcode == "bm" {
+                    Operation::BestMoves(moves)
+                } else {
+                    Operation::AvoidMoves(moves)
+                }
+            }
+            "pv" => {
+                let mut board = self.board;
+                let mut moves = Vec::new();
+                for san in &operands {
+                    let mv = parse_move(&opcode, &board, san)?;
+                    board.play_move(mv);
+                    moves.push(mv);
+                }
+                Operation::PredictedVariation(moves)
+            }
+            "id" => Operation::Id(single_operand()?),
+            "dm" => Operation::DirectMate(
+                single_operand()?
+                    .parse()
+                    .ok()
+                    .filter(|moves| *moves > 0)
+                    .ok_or_else(invalid_operand)?,
+            ),
+            "acd" => {
+                Operation::AnalysisDepth(single_operand()?.parse().map_err(|_| invalid_operand())?)
+            }
+            "ce" => Operation::CentipawnEvaluation(
+                single_operand()?.parse().map_err(|_| invalid_operand())?,
+            ),
+            _ => match comment_index(&opcode) {
+                Some(index) => Operation::Comment(index, single_operand()?),
+                None => Operation::Other { opcode, operands },
+            },
+        };
+
+        Ok(operation)
+    }
+
+    /// Writes the record back, moves in SAN. The move counters are not part of
+    /// the position, they are only written if given as operations.
+    pub fn to_epd(&self) -> String {
+        let fen = self.board.to_fen();
+        let mut epd = fen.split(' ').take(4).collect::<Vec<_>>().join(" ");
+
+        for operation in &self.operations {
+            let (opcode, operands) = match operation {
+                Operation::BestMoves(moves) => ("bm".to_string(), self.moves_to_san(moves, false)),
+                Operation::AvoidMoves(moves) => ("am".to_string(), self.moves_to_san(moves, false)),
+                Operation::PredictedVariation(moves) => {
+                    ("pv".to_string(), self.moves_to_san(moves, true))
+                }
+                Operation::Id(id) => ("id".to_string(), vec![quote(id)]),
+                Operation::Comment(index, comment) => (format!("c{}", index), vec![quote(comment)]),
+                Operation::DirectMate(moves) => ("dm".to_string(), vec![moves.to_string()]),
+                Operation::AnalysisDepth(depth) => ("acd".to_string(), vec![depth.to_string()]),
+                Operation::CentipawnEvaluation(score) => {
+                    ("ce".to_string(), vec![score.to_string()])
+                }
+                Operation::Other { opcode, operands } => (
+                    opcode.clone(),
+                    operands
+                        .iter()
+                        .map(|operand| match operand.contains(char::is_whitespace) {
+                            true => quote(operand),
+                            false => operand.clone(),
+                        })
+                        .collect(),
+                ),
+            };
+
+            epd.push(' ');
+            epd.push_str(&opcode);
+            for operand in operands {
+                epd.push(' ');
+                epd.push_str(&operand);
+            }
+            epd.push(';');
+        }
+
+        epd
+    }
+
+    /// SAN of the moves, played one after the other when `in_sequence` is set,
+    /// or all from the position otherwise.
+    fn moves_to_san(&self, moves: &[Move], in_sequence: bool) -> Vec<String> {
+        let mut board = self.board;
+        moves
+            .iter()
+            .map(|&mv| {
+                let san = move_to_san(&board, mv);
+                if in_sequence {
+                    board.play_move(mv);
+                }
+                san
+            })
+            .collect()
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operations
+            .iter()
+            .find_map(|operation| match operation {
+                Operation::Id(id) => Some(id.as_str()),
+                _ => None,
+            })
+    }
+
+    pub fn best_moves(&self) -> Option<&[Move]> {
+        self.operations
+            .iter()
+            .find_map(|operation| match operation {
+                Operation::BestMoves(moves) => Some(moves.as_slice()),
+                _ => None,
+            })
+    }
+
+    pub fn avoid_moves(&self) -> Option<&[Move]> {
+        self.operations
+            .iter()
+            .find_map(|operation| match operation {
+                Operation::AvoidMoves(moves) => Some(moves.as_slice()),
+                _ => None,
+            })
+    }
+
+    pub fn direct_mate(&self) -> Option<u32> {
+        self.operations
+            .iter()
+            .find_map(|operation| match operation {
+                Operation::DirectMate(moves) => Some(*moves),
+                _ => None,
+            })
+    }
+
+    /// Whether a search answer solves the position: it must be one of the best
+    /// moves, none of the moves to avoid, and mate quickly enough when a direct
+    /// mate is expected. None if the record does not describe any test.
+    pub fn is_solved_by(&self, best_move: Move, score: i32) -> Option<bool> {
+        let best_moves = self.best_moves();
+        let avoid_moves = self.avoid_moves();
+        let direct_mate = self.direct_mate();
+
+        if best_moves.is_none() && avoid_moves.is_none() && direct_mate.is_none() {
+            return None;
+        }
+
+        let mate_in = (score >= MATE_THRESHOLD).then(|| (MATE_SCORE - score + 1) as u32 / 2);
+
+        Some(
+            best_moves.is_none_or(|moves| moves.contains(&best_move))
+                && avoid_moves.is_none_or(|moves| !moves.contains(&best_move))
+                && direct_mate.is_none_or(|moves| mate_in.is_some_and(|mate_in| mate_in <= moves)),
+        )
+    }
+}
+
+fn parse_move(opcode: &str, board: &Board, san: &str) -> Result<Move, EpdError> {
+    parse_san(board, san, SanMode::Lenient).map_err(|error| EpdError::InvalidMove {
+        opcode: opcode.to_string(),
+        error,
+    })
+}
+
+fn comment_index(opcode: &str) -> Option<u8> {
+    match opcode.as_bytes() {
+        [b'c', digit @ b'0'..=b'9'] => Some(digit - b'0'),
+        _ => None,
+    }
+}
+
+fn quote(operand: &str) -> String {
+    format!("\"{}\"", operand.replace('"', ""))
+}
+
+/// Splits the operations part of a record into opcodes and operands. The last
+/// operation is often not terminated by a semicolon, which is tolerated.
+fn split_operations(operations: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut result = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = operations.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        match chars.next() {
+            Some('"') => {
+                let mut operand = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => operand.push(c),
+                        None => return Err(EpdError::UnterminatedString),
+                    }
+                }
+                words.push(operand);
+            }
+            Some(c) if c != ';' => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ';') {
+                    word.push(c);
+                }
+                words.push(word);
+            }
+            end => {
+                if !words.is_empty() {
+                    let opcode = words.remove(0);
+                    result.push((opcode, std::mem::take(&mut words)));
+                }
+                if end.is_none() {
+                    return Ok(result);
+                }
+            }
+        }
+    }
+}
+
+/// Result of a search on a test suite position.
+#[derive(Debug, Clone)]
+pub struct SuiteOutcome {
+    pub id: String,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    /// None when the record does not describe any test.
+    pub solved: Option<bool>,
+}
+
+/// Searches every position of a test suite with the given limits, printing
+/// whether each one is solved, and returns the outcomes.
+pub fn run_suite(positions: &[Epd], limits: SearchLimits) -> Vec<SuiteOutcome> {
+    let stop = AtomicBool::new(false);
+    let mut tt = TranspositionTable::new(DEFAULT_HASH_SIZE_MB);
+    let mut outcomes = Vec::new();
+
+    for (index, position) in positions.iter().enumerate() {
+        tt.clear();
+        let result =
+            Search::new(limits, &stop, &mut tt).run(&Position::new(position.board), |_| {});
+
+        let id = position
+            .id()
+            .map_or_else(|| format!("#{}", index + 1), str::to_string);
+        let best_move = result.as_ref().map(|result| result.best_move);
+        let score = result.as_ref().map_or(0, |result| result.score);
+        let solved = best_move.and_then(|mv| position.is_solved_by(mv, score));
+
+        let answer = best_move.map_or("none".to_string(), |mv| move_to_san(&position.board, mv));
+        let expected = position
+            .best_moves()
+            .map(|moves| format!("bm {}", position.moves_to_san(moves, false).join(" ")))
+            .or_else(|| {
+                position
+                    .avoid_moves()
+                    .map(|moves| format!("am {}", position.moves_to_san(moves, false).join(" ")))
+            })
+            .or_else(|| position.direct_mate().map(|moves| format!("dm {}", moves)))
+            .unwrap_or_default();
+
+        let status = match solved {
+            Some(true) => "solved",
+            Some(false) => "FAILED",
+            None => "no test",
+        };
+        println!(
+            "{:<16} {:<7} {:<8} score {:>6}  expected {}",
+            id, status, answer, score, expected
+        );
+
+        outcomes.push(SuiteOutcome {
+            id,
+            best_move,
+            score,
+            solved,
+        });
+    }
+
+    let tests = outcomes
+        .iter()
+        .filter(|outcome| outcome.solved.is_some())
+        .count();
+    let solved = outcomes
+        .iter()
+        .filter(|outcome| outcome.solved == Some(true))
+        .count();
+    println!("\nSolved {}/{}", solved, tests);
+
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAC_001: &str =
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";";
+
+    #[test]
+    fn records_are_parsed() {
+        let epd = Epd::from_epd(WAC_001).unwrap();
+
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(epd.best_moves().unwrap()[0].to_uci(), "g3g6");
+        assert_eq!(epd.board.halfmove_clock, 0);
+        assert_eq!(epd.to_epd(), WAC_001);
+
+        let epd = Epd::from_epd(
+            "4k3/8/8/8/8/8/8/R3K3 w Q - am O-O-O; c0 \"castling  is  fine\"; dm 2; \
+             pv Ra7 Kf8 Ra8+; acd 12; ce -35; hmvc 7; fmvn 42; sv Kd1 Ke1",
+        )
+        .unwrap();
+
+        assert_eq!(epd.avoid_moves().unwrap()[0].to_uci(), "e1c1");
+        assert_eq!(epd.direct_mate(), Some(2));
+        assert_eq!(epd.board.halfmove_clock, 7);
+        assert_eq!(epd.board.fullmove_number, 42);
+        assert_eq!(
+            epd.operations[1],
+            Operation::Comment(0, "castling  is  fine".to_string())
+        );
+        assert_eq!(
+            epd.operations.last(),
+            Some(&Operation::Other {
+                opcode: "sv".to_string(),
+                operands: vec!["Kd1".to_string(), "Ke1".to_string()],
+            })
+        );
+        assert_eq!(
+            epd.to_epd(),
+            "4k3/8/8/8/8/8/8/R3K3 w Q - am O-O-O; c0 \"castling  is  fine\"; dm 2; \
+             pv Ra7 Kf8 Ra8+; acd 12; ce -35; hmvc 7; fmvn 42; sv Kd1 Ke1;"
+        );
+    }
+
+    #[test]
+    fn invalid_records_are_rejected() {
+        assert!(matches!(
+            Epd::from_epd("2rr3k/pp3pp1 w - -"),
+            Err(EpdError::InvalidPosition(_))
+        ));
+        assert_eq!(
+            Epd::from_epd("4k3/8/8/8/8/8/8/4K3 w - - bm Kd8;").unwrap_err(),
+            EpdError::InvalidMove {
+                opcode: "bm".to_string(),
+                error: SanError::IllegalMove("Kd8".to_string()),
+            }
+        );
+        assert_eq!(
+            Epd::from_epd("4k3/8/8/8/8/8/8/4K3 w - - dm two;").unwrap_err(),
+            EpdError::InvalidOperand {
+                opcode: "dm".to_string(),
+                operand: "two".to_string(),
+            }
+        );
+        assert_eq!(
+            Epd::from_epd("4k3/8/8/8/8/8/8/4K3 w - - id \"oops;").unwrap_err(),
+            EpdError::UnterminatedString
+        );
+    }
+
+    #[test]
+    fn suites_are_run() {
+        let positions = [
+            WAC_001,
+            "6k1/5ppp/8/8/8/8/8/1R4K1 w - - dm 1; id \"back rank\";",
+            "6k1/5ppp/8/8/8/8/8/1R4K1 w - - am Rb8; id \"avoid mate\";",
+        ]
+        .map(|epd| Epd::from_epd(epd).unwrap());
+
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..SearchLimits::default()
+        };
+        let outcomes = run_suite(&positions, limits);
+
+        let solved: Vec<_> = outcomes.iter().map(|outcome| outcome.solved).collect();
+        assert_eq!(solved, vec![Some(true), Some(true), Some(false)]);
+        assert_eq!(outcomes[1].id, "back rank");
+    }
+}
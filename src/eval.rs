@@ -0,0 +1,357 @@
+use crate::bitboard::Bitboard;
+use crate::board::*;
+use crate::squares::Square;
+
+/// Material values, indexed by Piece, for the middlegame and the endgame.
+pub const MG_PIECE_VALUES: [i32; 6] = [82, 337, 365, 477, 1025, 0];
+pub const EG_PIECE_VALUES: [i32; 6] = [94, 281, 297, 512, 936, 0];
+
+/// Contribution of each piece to the game phase. The phase goes from 24 (all pieces
+/// on the board, pure middlegame) to 0 (kings and pawns only, pure endgame).
+const PHASE_INCREMENTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// Piece-square tables (from Ronald Friederich's PeSTO), for white pieces.
+// They follow the squares layout: a8 is the first entry, h1 the last one.
+// Black pieces use the vertically mirrored square.
+
+#[rustfmt::skip]
+const MG_PAWN_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     98, 134,  61,  95,  68, 126,  34, -11,
+     -6,   7,  26,  31,  65,  56,  25, -20,
+    -14,  13,   6,  21,  23,  12,  17, -23,
+    -27,  -2,  -5,  12,  17,   6,  10, -25,
+    -26,  -4,  -4, -10,   3,   3,  33, -12,
+    -35,  -1, -20, -23, -15,  24,  38, -22,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const EG_PAWN_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const MG_KNIGHT_TABLE: [i32; 64] = [
+    -167, -89, -34, -49,  61, -97, -15, -107,
+     -73, -41,  72,  36,  23,  62,   7,  -17,
+     -47,  60,  37,  65,  84, 129,  73,   44,
+      -9,  17,  19,  53,  37,  69,  18,   22,
+     -13,   4,  16,  13,  28,  19,  21,   -8,
+     -23,  -9,  12,  10,  19,  17,  25,  -16,
+     -29, -53, -12,  -3,  -1,  18, -14,  -19,
+    -105, -21, -58, -33, -17, -28, -19,  -23,
+];
+
+#[rustfmt::skip]
+const EG_KNIGHT_TABLE: [i32; 64] = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+
+#[rustfmt::skip]
+const MG_BISHOP_TABLE: [i32; 64] = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+
+#[rustfmt::skip]
+const EG_BISHOP_TABLE: [i32; 64] = [
+    -14, -21, -11,  -8,  -7,  -9, -17, -24,
+     -8,  -4,   7, -12,  -3, -13,  -4, -14,
+      2,  -8,   0,  -1,  -2,   6,   0,   4,
+     -3,   9,  12,   9,  14,  10,   3,   2,
+     -6,   3,  13,  19,   7,  10,  -3,  -9,
+    -12,  -3,   8,  10,  13,   3,  -7, -15,
+    -14, -18,  -7,  -1,   4,  -9, -15, -27,
+    -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+
+#[rustfmt::skip]
+const MG_ROOK_TABLE: [i32; 64] = [
+     32,  42,  32,  51,  63,   9,  31,  43,
+     27,  32,  58,  62,  80,  67,  26,  44,
+     -5,  19,  26,  36,  17,  45,  61,  16,
+    -24, -11,   7,  26,  24,  35,  -8, -20,
+    -36, -26, -12,  -1,   9,  -7,   6, -23,
+    -45, -25, -16, -17,   3,   0,  -5, -33,
+    -44, -16, -20,  -9,  -1,  11,  -6, -71,
+    -19, -13,   1,  17,  16,   7, -37, -26,
+];
+
+#[rustfmt::skip]
+const EG_ROOK_TABLE: [i32; 64] = [
+     13,  10,  18,  15,  12,  12,   8,   5,
+     11,  13,  13,  11,  -3,   3,   8,   3,
+      7,   7,   7,   5,   4,  -3,  -5,  -3,
+      4,   3,  13,   1,   2,   1,  -1,   2,
+      3,   5,   8,   4,  -5,  -6,  -8, -11,
+     -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+     -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+     -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+
+#[rustfmt::skip]
+const MG_QUEEN_TABLE: [i32; 64] = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+
+#[rustfmt::skip]
+const EG_QUEEN_TABLE: [i32; 64] = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+
+#[rustfmt::skip]
+const MG_KING_TABLE: [i32; 64] = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+
+#[rustfmt::skip]
+const EG_KING_TABLE: [i32; 64] = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+const MG_TABLES: [[i32; 64]; 6] = [
+    MG_PAWN_TABLE,
+    MG_KNIGHT_TABLE,
+    MG_BISHOP_TABLE,
+    MG_ROOK_TABLE,
+    MG_QUEEN_TABLE,
+    MG_KING_TABLE,
+];
+
+const EG_TABLES: [[i32; 64]; 6] = [
+    EG_PAWN_TABLE,
+    EG_KNIGHT_TABLE,
+    EG_BISHOP_TABLE,
+    EG_ROOK_TABLE,
+    EG_QUEEN_TABLE,
+    EG_KING_TABLE,
+];
+
+/// Evaluation terms which only depend on each piece and its square,
+/// and can therefore be updated incrementally as pieces move.
+/// Scores are given from white's point of view.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Evaluation {
+    mg_score: i32,
+    eg_score: i32,
+    phase: i32,
+}
+
+impl Evaluation {
+    /// Computes the evaluation terms from scratch.
+    pub fn new(pieces: &[Bitboard; 12]) -> Self {
+        let mut evaluation = Self::default();
+
+        for side in [Side::White, Side::Black] {
+            for piece in PIECES {
+                for square in pieces[piece as usize + side as usize * PIECE_COUNT] {
+                    evaluation.add_piece(piece, side, square);
+                }
+            }
+        }
+
+        evaluation
+    }
+
+    pub fn add_piece(&mut self, piece: Piece, side: Side, square: Square) {
+        let (mg, eg) = piece_square_values(piece, side, square);
+
+        self.mg_score += mg;
+        self.eg_score += eg;
+        self.phase += PHASE_INCREMENTS[piece as usize];
+    }
+
+    pub fn remove_piece(&mut self, piece: Piece, side: Side, square: Square) {
+        let (mg, eg) = piece_square_values(piece, side, square);
+
+        self.mg_score -= mg;
+        self.eg_score -= eg;
+        self.phase -= PHASE_INCREMENTS[piece as usize];
+    }
+
+    /// Blends the middlegame and endgame scores according to the game phase,
+    /// and returns the result from `side` point of view.
+    pub fn score(&self, side: Side) -> i32 {
+        // Early promotions can push the phase over its maximum.
+        let mg_phase = self.phase.min(MAX_PHASE);
+        let eg_phase = MAX_PHASE - mg_phase;
+
+        let score = (self.mg_score * mg_phase + self.eg_score * eg_phase) / MAX_PHASE;
+
+        match side {
+            Side::White => score,
+            Side::Black => -score,
+            Side::Both => unreachable!(),
+        }
+    }
+}
+
+/// Middlegame and endgame values (material included) of a piece on a square,
+/// from white's point of view.
+fn piece_square_values(piece: Piece, side: Side, square: Square) -> (i32, i32) {
+    let piece_index = piece as usize;
+
+    match side {
+        Side::White => (
+            MG_PIECE_VALUES[piece_index] + MG_TABLES[piece_index][square.index()],
+            EG_PIECE_VALUES[piece_index] + EG_TABLES[piece_index][square.index()],
+        ),
+        Side::Black => {
+            let square = square.flip().index();
+            (
+                -MG_PIECE_VALUES[piece_index] - MG_TABLES[piece_index][square],
+                -EG_PIECE_VALUES[piece_index] - EG_TABLES[piece_index][square],
+            )
+        }
+        Side::Both => unreachable!(),
+    }
+}
+
+/// Static evaluation of the position, from the side to move point of view.
+pub fn evaluate(board: &Board) -> i32 {
+    board.evaluation.score(board.side_to_move)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fens;
+    use crate::move_generator::generate_moves;
+
+    const FENS: [&str; 4] = [
+        fens::STARTING_BOARD_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+    ];
+
+    /// Same position with the colours swapped, e.g. a white pawn on e2
+    /// becomes a black pawn on e7.
+    fn mirror_fen(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect()
+        };
+
+        let placement: Vec<String> = fields[0].rsplit('/').map(swap_case).collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        let en_passant: String = fields[3]
+            .chars()
+            .map(|c| match c {
+                '3' => '6',
+                '6' => '3',
+                c => c,
+            })
+            .collect();
+
+        format!(
+            "{} {} {} {} {}",
+            placement.join("/"),
+            side,
+            swap_case(fields[2]),
+            en_passant,
+            fields[4..].join(" ")
+        )
+    }
+
+    #[test]
+    fn incremental_evaluation_matches_a_full_computation() {
+        for fen in FENS {
+            let mut board = Board::from_fen(fen).unwrap();
+
+            for ply in 0..8 {
+                let moves = generate_moves(&board);
+                for mv in moves.moves() {
+                    let mut after = board;
+                    after.make_move(*mv);
+                    assert_eq!(
+                        after.evaluation,
+                        Evaluation::new(&after.pieces),
+                        "{} {}",
+                        fen,
+                        mv
+                    );
+                }
+
+                if moves.is_empty() {
+                    break;
+                }
+                board.play_move(moves.moves()[ply * 7 % moves.len()]);
+            }
+        }
+    }
+
+    #[test]
+    fn mirrored_positions_get_opposite_scores() {
+        for fen in FENS {
+            let board = Board::from_fen(fen).unwrap();
+            let mirrored = Board::from_fen(&mirror_fen(fen)).unwrap();
+
+            assert_eq!(
+                mirrored.evaluation.score(Side::White),
+                -board.evaluation.score(Side::White),
+                "{}",
+                fen
+            );
+            assert_eq!(evaluate(&mirrored), evaluate(&board));
+        }
+    }
+}
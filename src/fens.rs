@@ -1,10 +1,67 @@
 #![allow(dead_code)]
-pub const EMPTY_BOARD_FEN: &str = "8/8/8/8/8/8/8/8 w - - -";
 pub const STARTING_BOARD_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 pub const SCOTCH_GAME_FEN: &str =
     "r1bqkbnr/pppp1ppp/2n5/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R b KQkq d3 0 3";
 pub const EPAULETTE_MATE_FEN: &str = "5rkr/8/8/8/8/8/8/1Q4K1 w - - 0 1";
-pub const BACKRANK_MATE_FEN: &str = "6k1/5ppp/8/8/8/8/8/1rk5 w - - 0 1";
-pub const LADDER_MATE_FEN: &str = "6k1/R7/2R5/8/8/8/8/8 w - - 0 1";
+pub const BACKRANK_MATE_FEN: &str = "6k1/5ppp/8/8/8/8/8/1R4K1 w - - 0 1";
+pub const LADDER_MATE_FEN: &str = "6k1/R7/2R5/8/8/8/8/K7 w - - 0 1";
 pub const CASTLE_MATE_FEN: &str = "8/8/8/8/8/8/R7/R3K1k1 w Q - 0 1";
 pub const PILLSBURY_MATE_FEN: &str = "5rk1/5p1p/5p1B/8/8/8/8/K6R w - - 0 1";
+
+/// Number of Chess960 starting positions.
+pub const CHESS960_POSITION_COUNT: u32 = 960;
+
+/// FEN of a Chess960 starting position, from its standard (Scharnagl) index.
+/// Index 518 is the standard chess starting position.
+pub fn chess960_fen(index: u32) -> Option<String> {
+    if index >= CHESS960_POSITION_COUNT {
+        return None;
+    }
+
+    // Where the two knights go among the five squares left by the bishops and the queen.
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+
+    let mut back_rank = [' '; 8];
+    let mut n = index as usize;
+
+    // Light-squared bishop on b, d, f or h, then dark-squared one on a, c, e or g.
+    back_rank[n % 4 * 2 + 1] = 'b';
+    n /= 4;
+    back_rank[n % 4 * 2] = 'b';
+    n /= 4;
+
+    let empty_files = |back_rank: &[char; 8]| -> Vec<usize> {
+        (0..8).filter(|file| back_rank[*file] == ' ').collect()
+    };
+
+    back_rank[empty_files(&back_rank)[n % 6]] = 'q';
+    n /= 6;
+
+    let empty = empty_files(&back_rank);
+    let (first, second) = KNIGHTS[n];
+    back_rank[empty[first]] = 'n';
+    back_rank[empty[second]] = 'n';
+
+    // The king always stands between the rooks.
+    for (file, piece) in empty_files(&back_rank).into_iter().zip(['r', 'k', 'r']) {
+        back_rank[file] = piece;
+    }
+
+    let black: String = back_rank.iter().collect();
+    Some(format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+        black,
+        black.to_ascii_uppercase()
+    ))
+}
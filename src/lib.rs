@@ -0,0 +1,42 @@
+//! Bitboard chess engine: board representation, legal move generation,
+//! perft, SAN, PGN and EPD, evaluation and search, plus the UCI front end used by the binary.
+
+mod ascii_view;
+mod attacks;
+mod bitboard;
+mod board;
+mod epd;
+mod eval;
+mod fens;
+mod move_generator;
+mod perft;
+mod pgn;
+mod san;
+mod search;
+mod squares;
+mod tt;
+mod uci;
+
+mod codegen;
+mod rand;
+mod zobrist;
+
+pub use attacks::{Attacks, SliderBackend, SliderLayout};
+pub use bitboard::{Bitboard, Direction};
+pub use board::{
+    Board, DrawReason, FenError, GameStatus, Piece, Position, Side, SidedPiece, UciMoveError, Undo,
+};
+pub use epd::{run_suite, Epd, EpdError, Operation, SuiteOutcome};
+pub use eval::{evaluate, Evaluation};
+pub use fens::{chess960_fen, CHESS960_POSITION_COUNT, STARTING_BOARD_FEN};
+pub use move_generator::{
+    generate_captures, generate_moves, generate_pseudo_legal_moves, is_in_check, CastlingNotation,
+    Move, Moves,
+};
+pub use perft::{bench, divide, perft, perft_unmake, MakeMode};
+pub use pgn::{read_game, read_games, Game, GameMove, GameResult, Line, PgnError, PgnErrorKind};
+pub use san::{move_to_san, parse_san, SanError, SanMode};
+pub use search::{Search, SearchLimits, SearchResult, MATE_SCORE};
+pub use squares::Square;
+pub use tt::TranspositionTable;
+pub use uci::Uci;
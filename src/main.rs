@@ -1,48 +1,109 @@
-#![feature(variant_count)]
-
-use std::time::Instant;
-
-use ascii_view::*;
-use attacks::*;
-use board::*;
-use move_generator::generate_moves;
-
-mod ascii_view;
-mod attacks;
-mod bitboard;
-mod board;
-mod codegen;
-mod fens;
-mod move_generator;
-mod rand;
-mod squares;
-
-fn run_depth(board: &Board, depth: i32, results: &mut [usize; 10]) {
-    if depth == 0 {
+use std::time::{Duration, Instant};
+
+use chess_engine::{
+    bench, divide, perft, run_suite, Attacks, Board, Epd, MakeMode, SearchLimits, Uci,
+    STARTING_BOARD_FEN,
+};
+
+/// Usage:
+///   chess_engine perft <depth> [fen]
+///   chess_engine divide <depth> [fen]
+///   chess_engine bench [copy|unmake]
+///   chess_engine epd <file> [milliseconds per position]
+/// Without arguments, starts the UCI protocol on stdin/stdout.
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.is_empty() {
+        Uci::new().run();
+        return;
+    }
+
+    let command = args[0].as_str();
+
+    if command == "bench" {
+        let mode = match args.get(1).map(String::as_str) {
+            Some("unmake") => MakeMode::UnmakeMove,
+            _ => MakeMode::CopyMake,
+        };
+        println!(
+            "Slider attacks backend: {:?}, move tree walked with: {:?}\n",
+            Attacks::global().backend(),
+            mode
+        );
+        bench(mode);
         return;
     }
-    // This will be easier to play moves from multiple threads
-    let mut my_board = board.clone();
 
-    let moves = generate_moves(&my_board);
+    if command == "epd" {
+        run_epd_suite(&args[1..]);
+        return;
+    }
+
+    let depth = args.get(1).and_then(|d| d.parse().ok()).unwrap_or(5);
+    let fen = if args.len() > 2 {
+        args[2..].join(" ")
+    } else {
+        STARTING_BOARD_FEN.to_string()
+    };
+
+    let board = match Board::from_fen(&fen) {
+        Ok(board) => board,
+        Err(error) => {
+            eprintln!("Invalid FEN '{}': {}", fen, error);
+            return;
+        }
+    };
 
-    results[(depth - 1) as usize] += moves.len();
+    match command {
+        "perft" => {
+            let start_time = Instant::now();
+            let nodes = perft(&board, depth);
+            let dt = start_time.elapsed();
 
-    for mv in moves.moves() {
-        let old_board = my_board.play_move(*mv);
-        run_depth(&my_board, depth - 1, results);
-        my_board = old_board
+            println!(
+                "{} nodes - {}ms ({:.0} nps)",
+                nodes,
+                dt.as_millis(),
+                nodes as f64 / dt.as_secs_f64()
+            );
+        }
+        "divide" => {
+            divide(&board, depth);
+        }
+        _ => eprintln!("Unknown command: {}", command),
     }
 }
 
-fn main() {
-    let attacks = Attacks::new();
-    let board = Board::from_fen(fens::STARTING_BOARD_FEN, &attacks);
+fn run_epd_suite(args: &[String]) {
+    let Some(path) = args.first() else {
+        eprintln!("Missing EPD file");
+        return;
+    };
 
-    let mut result = [0usize; 10];
-    let start_time = Instant::now();
-    run_depth(&board, 5, &mut result);
-    let dt = start_time.elapsed();
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("Cannot read '{}': {}", path, error);
+            return;
+        }
+    };
+
+    let mut positions = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match Epd::from_epd(line) {
+            Ok(position) => positions.push(position),
+            Err(error) => eprintln!("Line {} skipped: {}", index + 1, error),
+        }
+    }
 
-    println!("{:?} - {}", result, dt.as_millis());
+    let milliseconds = args.get(1).and_then(|ms| ms.parse().ok()).unwrap_or(1000);
+    let limits = SearchLimits {
+        time: Some(Duration::from_millis(milliseconds)),
+        ..SearchLimits::default()
+    };
+    run_suite(&positions, limits);
 }
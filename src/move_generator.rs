@@ -1,7 +1,11 @@
+use std::fmt;
+
+use crate::attacks::Attacks;
 use crate::bitboard::*;
 use crate::board::*;
+use crate::squares::Square;
 
-/// Move encoding on an i32
+/// Move encoding on an u32
 ///
 /// 0000 0000 0000 0000 0000 0000 0011 1111 (    0x3F) -> Source square
 /// 0000 0000 0000 0000 0000 1111 1100 0000 (   0xFC0) -> Destination square
@@ -11,105 +15,192 @@ use crate::board::*;
 /// 0000 0000 0010 0000 0000 0000 0000 0000 (0x200000) -> Double push flag
 /// 0000 0000 0100 0000 0000 0000 0000 0000 (0x400000) -> En-passant flag
 /// 0000 0000 1000 0000 0000 0000 0000 0000 (0x800000) -> Castling flag
-
-const SRC_SQUARE_MASK: i32 = 0x3F;
-const DST_SQUARE_MASK: i32 = 0xFC0;
-const PIECE_MASK: i32 = 0xF000;
-const PROMOTION_PIECE_MASK: i32 = 0xF0000;
-const CAPTURE_FLAG_MASK: i32 = 0x100000;
-const DOUBLE_PUSH_FLAG_MASK: i32 = 0x200000;
-const EN_PASSANT_FLAG_MASK: i32 = 0x400000;
-const CASTLING_FLAG_MASK: i32 = 0x800000;
-
-const SRC_SQUARE_BIT_OFFSET: i32 = 0;
-const DST_SQUARE_BIT_OFFSET: i32 = 6;
-const PIECE_BIT_OFFSET: i32 = 12;
-const PROMOTION_PIECE_BIT_OFFSET: i32 = 16;
-const CAPTURE_FLAG_BIT_OFFSET: i32 = 20;
-const DOUBLE_PUSH_FLAG_BIT_OFFSET: i32 = 21;
-const EN_PASSANT_FLAG_BIT_OFFSET: i32 = 22;
-const CASTLING_FLAG_BIT_OFFSET: i32 = 23;
-
-pub struct Move {}
+/// 0011 1111 0000 0000 0000 0000 0000 0000 (0x3F000000) -> Castling rook square
+const SRC_SQUARE_MASK: u32 = 0x3F;
+const DST_SQUARE_MASK: u32 = 0xFC0;
+const PIECE_MASK: u32 = 0xF000;
+const PROMOTION_PIECE_MASK: u32 = 0xF0000;
+const CAPTURE_FLAG_MASK: u32 = 0x100000;
+const DOUBLE_PUSH_FLAG_MASK: u32 = 0x200000;
+const EN_PASSANT_FLAG_MASK: u32 = 0x400000;
+const CASTLING_FLAG_MASK: u32 = 0x800000;
+const CASTLING_ROOK_SQUARE_MASK: u32 = 0x3F000000;
+
+const SRC_SQUARE_BIT_OFFSET: u32 = 0;
+const DST_SQUARE_BIT_OFFSET: u32 = 6;
+const PIECE_BIT_OFFSET: u32 = 12;
+const PROMOTION_PIECE_BIT_OFFSET: u32 = 16;
+const CAPTURE_FLAG_BIT_OFFSET: u32 = 20;
+const DOUBLE_PUSH_FLAG_BIT_OFFSET: u32 = 21;
+const EN_PASSANT_FLAG_BIT_OFFSET: u32 = 22;
+const CASTLING_FLAG_BIT_OFFSET: u32 = 23;
+const CASTLING_ROOK_SQUARE_BIT_OFFSET: u32 = 24;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move(u32);
 
 impl Move {
-    pub fn encode(piece: Piece, src_square: i32, dst_square: i32) -> i32 {
-        let mut mv = 0i32;
+    /// Not a real move (a8 to a8), used as a placeholder.
+    pub const NULL: Move = Move(0);
+
+    pub fn encode(piece: Piece, src_square: Square, dst_square: Square) -> Self {
+        let mut mv = 0u32;
 
-        mv |= src_square << SRC_SQUARE_BIT_OFFSET;
-        mv |= dst_square << DST_SQUARE_BIT_OFFSET;
-        mv |= (piece as i32) << PIECE_BIT_OFFSET;
+        mv |= (src_square.index() as u32) << SRC_SQUARE_BIT_OFFSET;
+        mv |= (dst_square.index() as u32) << DST_SQUARE_BIT_OFFSET;
+        mv |= (piece as u32) << PIECE_BIT_OFFSET;
 
-        mv
+        Self(mv)
     }
 
-    pub fn encode_capture(piece: Piece, src_square: i32, dst_square: i32) -> i32 {
-        Move::encode(piece, src_square, dst_square) | 1 << CAPTURE_FLAG_BIT_OFFSET
+    pub fn encode_capture(piece: Piece, src_square: Square, dst_square: Square) -> Self {
+        Self(Move::encode(piece, src_square, dst_square).0 | 1 << CAPTURE_FLAG_BIT_OFFSET)
     }
 
-    pub fn encode_castling(piece: Piece, src_square: i32, dst_square: i32) -> i32 {
-        Move::encode(piece, src_square, dst_square) | 1 << CASTLING_FLAG_BIT_OFFSET
+    /// Castling is encoded as the king move. The rook initial square is needed
+    /// as well, since it is not always the corner one in Chess960.
+    pub fn encode_castling(
+        piece: Piece,
+        src_square: Square,
+        dst_square: Square,
+        rook_square: Square,
+    ) -> Self {
+        let mut mv = Move::encode(piece, src_square, dst_square).0;
+
+        mv |= 1 << CASTLING_FLAG_BIT_OFFSET;
+        mv |= (rook_square.index() as u32) << CASTLING_ROOK_SQUARE_BIT_OFFSET;
+
+        Self(mv)
     }
 
-    pub fn encode_en_passant(piece: Piece, src_square: i32, dst_square: i32) -> i32 {
-        Move::encode(piece, src_square, dst_square) | 1 << EN_PASSANT_FLAG_BIT_OFFSET
+    pub fn encode_en_passant(piece: Piece, src_square: Square, dst_square: Square) -> Self {
+        Self(Move::encode(piece, src_square, dst_square).0 | 1 << EN_PASSANT_FLAG_BIT_OFFSET)
     }
 
     pub fn encode_promotion(
         piece: Piece,
-        src_square: i32,
-        dst_square: i32,
+        src_square: Square,
+        dst_square: Square,
+        promotion_piece: Piece,
         with_capture: bool,
-    ) -> i32 {
-        let mut mv = Move::encode(piece, src_square, dst_square);
+    ) -> Self {
+        let mut mv = Move::encode(piece, src_square, dst_square).0;
 
-        mv |= 0xF << PROMOTION_PIECE_BIT_OFFSET;
-        mv |= (with_capture as i32) << CAPTURE_FLAG_BIT_OFFSET;
+        mv |= (promotion_piece as u32) << PROMOTION_PIECE_BIT_OFFSET;
+        mv |= (with_capture as u32) << CAPTURE_FLAG_BIT_OFFSET;
 
-        mv
+        Self(mv)
     }
 
-    pub fn encode_double_push(piece: Piece, src_square: i32, dst_square: i32) -> i32 {
-        Move::encode(piece, src_square, dst_square) | 1 << DOUBLE_PUSH_FLAG_BIT_OFFSET
+    pub fn encode_double_push(piece: Piece, src_square: Square, dst_square: Square) -> Self {
+        Self(Move::encode(piece, src_square, dst_square).0 | 1 << DOUBLE_PUSH_FLAG_BIT_OFFSET)
     }
 
-    pub fn decode_src_square(mv: i32) -> i32 {
-        (mv & SRC_SQUARE_MASK) >> SRC_SQUARE_BIT_OFFSET
+    /// Raw encoding, e.g. to store the move in a compact way.
+    pub const fn to_bits(self) -> u32 {
+        self.0
     }
 
-    pub fn decode_dst_square(mv: i32) -> i32 {
-        (mv & DST_SQUARE_MASK) >> DST_SQUARE_BIT_OFFSET
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
     }
 
-    pub fn decode_piece(mv: i32) -> Piece {
-        Piece::from((mv & PIECE_MASK) >> PIECE_BIT_OFFSET)
+    pub const fn src_square(self) -> Square {
+        Square::new(((self.0 & SRC_SQUARE_MASK) >> SRC_SQUARE_BIT_OFFSET) as u8)
     }
 
-    pub fn decode_promotion_piece(mv: i32) -> Piece {
-        Piece::from((mv & PROMOTION_PIECE_MASK) >> PROMOTION_PIECE_BIT_OFFSET)
+    pub const fn dst_square(self) -> Square {
+        Square::new(((self.0 & DST_SQUARE_MASK) >> DST_SQUARE_BIT_OFFSET) as u8)
     }
 
-    pub fn is_promotion(mv: i32) -> bool {
-        mv & PROMOTION_PIECE_MASK != 0
+    pub fn piece(self) -> Piece {
+        Piece::from(((self.0 & PIECE_MASK) >> PIECE_BIT_OFFSET) as i32)
     }
 
-    pub fn is_capture(mv: i32) -> bool {
-        mv & CAPTURE_FLAG_MASK != 0
+    /// The piece the pawn turns into, None if the move is not a promotion.
+    /// (A pawn cannot promote to a pawn, so 0 is free to mean "no promotion".)
+    pub fn promotion_piece(self) -> Option<Piece> {
+        match (self.0 & PROMOTION_PIECE_MASK) >> PROMOTION_PIECE_BIT_OFFSET {
+            0 => None,
+            piece => Some(Piece::from(piece as i32)),
+        }
+    }
+
+    pub const fn is_promotion(self) -> bool {
+        self.0 & PROMOTION_PIECE_MASK != 0
     }
 
-    pub fn is_double_push(mv: i32) -> bool {
-        mv & DOUBLE_PUSH_FLAG_MASK != 0
+    pub const fn is_capture(self) -> bool {
+        self.0 & CAPTURE_FLAG_MASK != 0
     }
 
-    pub fn is_en_passant(mv: i32) -> bool {
-        mv & EN_PASSANT_FLAG_MASK != 0
+    pub const fn is_double_push(self) -> bool {
+        self.0 & DOUBLE_PUSH_FLAG_MASK != 0
+    }
+
+    pub const fn is_en_passant(self) -> bool {
+        self.0 & EN_PASSANT_FLAG_MASK != 0
+    }
+
+    pub const fn is_castling(self) -> bool {
+        self.0 & CASTLING_FLAG_MASK != 0
+    }
+
+    /// Initial square of the rook, for a castling move.
+    pub const fn castling_rook_square(self) -> Square {
+        Square::new(((self.0 & CASTLING_ROOK_SQUARE_MASK) >> CASTLING_ROOK_SQUARE_BIT_OFFSET) as u8)
+    }
+
+    /// Castling moves always bring the king to the g-file (king side)
+    /// or to the c-file (queen side), Chess960 included.
+    pub const fn is_king_side_castling(self) -> bool {
+        self.is_castling() && self.dst_square().file() == 6
+    }
+
+    /// Long algebraic notation, as used by the UCI protocol (e.g. e2e4, e7e8q, e1g1).
+    pub fn to_uci(self) -> String {
+        self.to_uci_with(CastlingNotation::KingToDestination)
+    }
+
+    pub fn to_uci_with(self, castling_notation: CastlingNotation) -> String {
+        let dst_square = match castling_notation {
+            CastlingNotation::KingTakesRook if self.is_castling() => self.castling_rook_square(),
+            _ => self.dst_square(),
+        };
+        let mut result = format!("{}{}", self.src_square(), dst_square);
+
+        if let Some(promotion_piece) = self.promotion_piece() {
+            result.push(match promotion_piece {
+                Piece::Knight => 'n',
+                Piece::Bishop => 'b',
+                Piece::Rook => 'r',
+                Piece::Queen => 'q',
+                Piece::Pawn | Piece::King => unreachable!(),
+            });
+        }
+
+        result
     }
+}
+
+/// How castling moves are written in long algebraic notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastlingNotation {
+    /// The king move, e.g. e1g1 (standard UCI)
+    KingToDestination,
+    /// The king "takes" its own rook, e.g. e1h1 (UCI_Chess960)
+    KingTakesRook,
+}
 
-    pub fn is_castling(mv: i32) -> bool {
-        mv & CASTLING_FLAG_MASK != 0
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_uci())
     }
 }
 
+/// Pieces a pawn can promote to.
+const PROMOTION_PIECES: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
 /// Check whether the given square is under attack.
 /// In order to do this,instead of checking if any of the pieces is attacking the square,
 /// we check if the square attacks any of the pieces as this piece.
@@ -117,104 +208,339 @@ impl Move {
 /// as a white pawn, the square attacks d6, which is easier to check.
 /// This also means we only do 6 attack lookups and 6 bitwise & instead of 16
 /// (plus the need to find the pieces inside the pieces bitboards)
-pub fn is_square_attacked(board: &Board, square: i32, attacking_side: Side) -> bool {
-    let occupancy = board.occupancies[Side::Both as usize];
-
-    let this_side = match attacking_side {
-        Side::Black => Side::White,
-        Side::White => Side::Black,
-        Side::Both => unreachable!(),
-    };
+pub fn is_square_attacked(board: &Board, square: Square, attacking_side: Side) -> bool {
+    is_square_attacked_with_occupancy(
+        board,
+        square,
+        attacking_side,
+        board.occupancies[Side::Both as usize],
+    )
+}
 
-    let as_pawn = board.attacks.get_pawn_attacks(square, this_side);
-    if bits_collide(as_pawn, board.bitboard(Piece::Pawn, attacking_side)) {
+/// Same as `is_square_attacked`, but sliding pieces see through the given occupancy
+/// instead of the board one.
+pub fn is_square_attacked_with_occupancy(
+    board: &Board,
+    square: Square,
+    attacking_side: Side,
+    occupancy: Bitboard,
+) -> bool {
+    let this_side = opponent_side(attacking_side);
+
+    let as_pawn = Attacks::global().get_pawn_attacks(square, this_side);
+    if as_pawn.collides(board.bitboard(Piece::Pawn, attacking_side)) {
         return true;
     }
 
-    let as_knight = board.attacks.get_knight_attacks(square);
-    if bits_collide(as_knight, board.bitboard(Piece::Knight, attacking_side)) {
+    let as_knight = Attacks::global().get_knight_attacks(square);
+    if as_knight.collides(board.bitboard(Piece::Knight, attacking_side)) {
         return true;
     }
 
-    let as_king = board.attacks.get_king_attacks(square);
-    if bits_collide(as_king, board.bitboard(Piece::King, attacking_side)) {
+    let as_king = Attacks::global().get_king_attacks(square);
+    if as_king.collides(board.bitboard(Piece::King, attacking_side)) {
         return true;
     }
 
-    let as_bishop = board.attacks.get_bishop_attacks(square, occupancy);
-    if bits_collide(as_bishop, board.bitboard(Piece::Bishop, attacking_side)) {
+    let as_bishop = Attacks::global().get_bishop_attacks(square, occupancy);
+    if as_bishop.collides(board.bitboard(Piece::Bishop, attacking_side)) {
         return true;
     }
 
-    let as_rook = board.attacks.get_rook_attacks(square, occupancy);
-    if bits_collide(as_rook, board.bitboard(Piece::Rook, attacking_side)) {
+    let as_rook = Attacks::global().get_rook_attacks(square, occupancy);
+    if as_rook.collides(board.bitboard(Piece::Rook, attacking_side)) {
         return true;
     }
 
-    let as_queen = board.attacks.get_queen_attacks(square, occupancy);
-    if bits_collide(as_queen, board.bitboard(Piece::Queen, attacking_side)) {
+    let as_queen = Attacks::global().get_queen_attacks(square, occupancy);
+    if as_queen.collides(board.bitboard(Piece::Queen, attacking_side)) {
         return true;
     }
 
     false
 }
 
+/// Bitboard of all the `attacking_side` pieces attacking the given square.
+/// This uses the same trick as `is_square_attacked`.
+pub fn attackers_of(
+    board: &Board,
+    square: Square,
+    attacking_side: Side,
+    occupancy: Bitboard,
+) -> Bitboard {
+    let this_side = opponent_side(attacking_side);
+
+    let queens = board.bitboard(Piece::Queen, attacking_side);
+    let bishops = board.bitboard(Piece::Bishop, attacking_side) | queens;
+    let rooks = board.bitboard(Piece::Rook, attacking_side) | queens;
+
+    (Attacks::global().get_pawn_attacks(square, this_side)
+        & board.bitboard(Piece::Pawn, attacking_side))
+        | (Attacks::global().get_knight_attacks(square)
+            & board.bitboard(Piece::Knight, attacking_side))
+        | (Attacks::global().get_king_attacks(square) & board.bitboard(Piece::King, attacking_side))
+        | (Attacks::global().get_bishop_attacks(square, occupancy) & bishops)
+        | (Attacks::global().get_rook_attacks(square, occupancy) & rooks)
+}
+
+/// Whether the king of the given side is currently attacked.
+/// Engines relying on pseudo-legal generation can use this after `Board::play_move`
+/// to reject moves leaving their own king in check.
+pub fn is_in_check(board: &Board, side: Side) -> bool {
+    board
+        .bitboard(Piece::King, side)
+        .lsb()
+        .is_some_and(|king_square| is_square_attacked(board, king_square, opponent_side(side)))
+}
+
 pub struct Moves {
-    moves: [i32; 256],
+    moves: [Move; 256],
     move_count: usize,
 }
 
+impl Default for Moves {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Moves {
     pub fn new() -> Self {
         Self {
-            moves: [0i32; 256],
+            moves: [Move::NULL; 256],
             move_count: 0,
         }
     }
 
-    pub fn push(&mut self, value: i32) {
+    pub fn push(&mut self, value: Move) {
         self.moves[self.move_count] = value;
         self.move_count += 1;
     }
 
-    pub fn moves(&self) -> &[i32] {
+    pub fn moves(&self) -> &[Move] {
         &self.moves[0..self.move_count]
     }
 
+    pub fn moves_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[0..self.move_count]
+    }
+
     pub fn len(&self) -> usize {
         self.move_count
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.move_count == 0
+    }
+}
+
+/// Restrictions a move has to comply with in order to be legal.
+/// They are computed once per position, then each piece generator only keeps
+/// the destination squares allowed by them.
+struct Legality {
+    /// None when the side has no king, nothing has to be kept safe then.
+    king_square: Option<Square>,
+
+    /// Squares the non-king pieces are allowed to move to.
+    /// When in check, this is the checking piece and the squares between it and our king,
+    /// on double check, this is empty. Otherwise, every square is allowed.
+    check_mask: Bitboard,
+
+    /// Our pieces pinned against our king. These can only move along the pin line.
+    pinned: Bitboard,
+
+    /// Whether en-passant captures need to be checked against discovered checks.
+    /// This is disabled for pseudo-legal generation.
+    strict: bool,
+}
+
+impl Legality {
+    fn pseudo_legal(board: &Board, side: Side) -> Self {
+        Self {
+            king_square: board.bitboard(Piece::King, side).lsb(),
+            check_mask: Bitboard::FULL,
+            pinned: Bitboard::EMPTY,
+            strict: false,
+        }
+    }
+
+    fn new(board: &Board, side: Side) -> Self {
+        // Without a king, there is nothing to keep safe.
+        let Some(king_square) = board.bitboard(Piece::King, side).lsb() else {
+            return Self::pseudo_legal(board, side);
+        };
+
+        let opponent = opponent_side(side);
+
+        let occupancy = board.occupancies[Side::Both as usize];
+        let my_occupancy = board.occupancies[side as usize];
+        let opponent_occupancy = board.occupancies[opponent as usize];
+
+        let checkers = attackers_of(board, king_square, opponent, occupancy);
+        let check_mask = match (checkers.count(), checkers.lsb()) {
+            (0, _) => Bitboard::FULL,
+            (1, Some(checker)) => checkers | Attacks::global().get_between(king_square, checker),
+            _ => Bitboard::EMPTY,
+        };
+
+        // Look for opponent sliders aiming at our king through our own pieces
+        // (the "snipers"). If exactly one piece stands between a sniper and our king,
+        // and it is ours, then it is pinned.
+        let queens = board.bitboard(Piece::Queen, opponent);
+        let bishops = board.bitboard(Piece::Bishop, opponent) | queens;
+        let rooks = board.bitboard(Piece::Rook, opponent) | queens;
+
+        let snipers = (Attacks::global().get_bishop_attacks(king_square, opponent_occupancy)
+            & bishops)
+            | (Attacks::global().get_rook_attacks(king_square, opponent_occupancy) & rooks);
+
+        let mut pinned = Bitboard::EMPTY;
+
+        for sniper_square in snipers {
+            let blockers = Attacks::global().get_between(king_square, sniper_square) & occupancy;
+            if blockers.count() == 1 && blockers.collides(my_occupancy) {
+                pinned |= blockers;
+            }
+        }
+
+        Self {
+            king_square: Some(king_square),
+            check_mask,
+            pinned,
+            strict: true,
+        }
+    }
+
+    fn is_double_check(&self) -> bool {
+        self.check_mask.is_empty()
+    }
+
+    /// Squares the (non-king) piece standing on `square` is allowed to move to.
+    fn targets(&self, square: Square) -> Bitboard {
+        match self.king_square {
+            Some(king_square) if self.pinned.contains(square) => {
+                self.check_mask & Attacks::global().get_line(king_square, square)
+            }
+            _ => self.check_mask,
+        }
+    }
+
+    /// En-passant captures are special as two pawns leave the same rank at once.
+    /// This can uncover an attack on our king that the pin detection cannot see,
+    /// e.g. with both pawns standing between our king and an opponent rook.
+    /// We simply check the sliders lines once the capture is made instead.
+    fn allows_en_passant(
+        &self,
+        board: &Board,
+        side: Side,
+        src_square: Square,
+        dst_square: Square,
+    ) -> bool {
+        let Some(king_square) = self.king_square.filter(|_| self.strict) else {
+            return true;
+        };
+
+        let victim_square = match side {
+            Side::White => dst_square.offset(8),
+            Side::Black => dst_square.offset(-8),
+            Side::Both => unreachable!(),
+        };
+
+        // When in check, the capture has to either block the check,
+        // or remove the pawn giving it.
+        let capture_squares = Bitboard::from_square(dst_square).with(victim_square);
+        if !self.check_mask.collides(capture_squares) {
+            return false;
+        }
+
+        let occupancy = board.occupancies[Side::Both as usize]
+            .without(src_square)
+            .without(victim_square)
+            .with(dst_square);
+
+        let opponent = opponent_side(side);
+        let queens = board.bitboard(Piece::Queen, opponent);
+        let bishops = board.bitboard(Piece::Bishop, opponent) | queens;
+        let rooks = board.bitboard(Piece::Rook, opponent) | queens;
+
+        !Attacks::global()
+            .get_bishop_attacks(king_square, occupancy)
+            .collides(bishops)
+            && !Attacks::global()
+                .get_rook_attacks(king_square, occupancy)
+                .collides(rooks)
+    }
 }
 
+/// Which kind of moves a generator should produce.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GenerationMode {
+    All,
+
+    /// Captures (en-passant included) and queen promotions only.
+    Captures,
+}
+
+/// Generates all the legal moves for the side to move.
 pub fn generate_moves(board: &Board) -> Moves {
+    let legality = Legality::new(board, board.side_to_move);
+    generate(board, &legality, GenerationMode::All)
+}
+
+/// Generates the legal captures and queen promotions for the side to move.
+/// This is what the quiescence search looks at, under-promotions and quiet moves
+/// are not generated at all.
+pub fn generate_captures(board: &Board) -> Moves {
+    let legality = Legality::new(board, board.side_to_move);
+    generate(board, &legality, GenerationMode::Captures)
+}
+
+/// Generates pseudo-legal moves for the side to move: pieces may leave their
+/// king in check (only the king itself avoids attacked squares).
+/// Callers are expected to check the legality of each move, e.g. using `is_in_check`
+/// once the move is played.
+pub fn generate_pseudo_legal_moves(board: &Board) -> Moves {
+    let legality = Legality::pseudo_legal(board, board.side_to_move);
+    generate(board, &legality, GenerationMode::All)
+}
+
+fn generate(board: &Board, legality: &Legality, mode: GenerationMode) -> Moves {
     let mut moves = Moves::new();
+    let side = board.side_to_move;
 
-    match board.side_to_move {
-        Side::White => {
-            generate_pawns(board, Side::White, &mut moves);
-            generate_king_castles(board, Side::White, &mut moves);
-        }
-        Side::Black => {
-            generate_pawns(board, Side::Black, &mut moves);
-            generate_king_castles(board, Side::Black, &mut moves);
+    // Squares the pieces (but the pawns) may move to, given the generation mode.
+    let destinations = match mode {
+        GenerationMode::All => Bitboard::FULL,
+        GenerationMode::Captures => board.occupancies[opponent_side(side) as usize],
+    };
+
+    // When the king is attacked twice, moving it is the only option.
+    if !legality.is_double_check() {
+        generate_pawns(board, side, legality, mode, &mut moves);
+        if mode == GenerationMode::All {
+            generate_king_castles(board, side, &mut moves);
         }
-        Side::Both => unreachable!(),
+        generate_knights(board, side, legality, destinations, &mut moves);
+        generate_bishops(board, side, legality, destinations, &mut moves);
+        generate_rooks(board, side, legality, destinations, &mut moves);
+        generate_queens(board, side, legality, destinations, &mut moves);
     }
 
-    generate_knights(board, board.side_to_move, &mut moves);
-    generate_bishops(board, board.side_to_move, &mut moves);
-    generate_rooks(board, board.side_to_move, &mut moves);
-    generate_queens(board, board.side_to_move, &mut moves);
-    generate_kings(board, board.side_to_move, &mut moves);
+    generate_kings(board, side, destinations, &mut moves);
 
     moves
 }
 
-fn generate_pawns(board: &Board, side: Side, moves: &mut Moves) {
+fn generate_pawns(
+    board: &Board,
+    side: Side,
+    legality: &Legality,
+    mode: GenerationMode,
+    moves: &mut Moves,
+) {
     // Cache relevant data
     let all_occupancies = board.occupancies[Side::Both as usize];
     let opp_occupancies = board.occupancies[opponent_side(side) as usize];
-    let en_passant_square = board.en_passant_square;
 
     // start_rank: This side's pawns start rank.
     //   This is given by the result of the integer division of the
@@ -223,10 +549,11 @@ fn generate_pawns(board: &Board, side: Side, moves: &mut Moves) {
     //   1 corresponds to rank 7 (black's start rank)
     //   ...
     //   6 corresponds to rank 2 (white's start rank)
-    // back_rank: The opponents "back rank". This is used to detect promotion
-    let (start_rank, back_rank) = match side {
-        Side::White => (6, 0),
-        Side::Black => (1, 7),
+    // promotion_rank: The rank right before the opponent's back rank.
+    //   Pawns moving from this rank promote.
+    let (start_rank, promotion_rank) = match side {
+        Side::White => (6, 1),
+        Side::Black => (1, 6),
         Side::Both => unreachable!(),
     };
 
@@ -236,33 +563,40 @@ fn generate_pawns(board: &Board, side: Side, moves: &mut Moves) {
         Side::Both => unreachable!(),
     };
 
-    let mut bitboard = board.bitboard(Piece::Pawn, side);
-
-    while bitboard != 0 {
-        let src_square = lsb_index(bitboard) as i32;
-        bitboard = pop_bit(bitboard, src_square);
+    // Under-promotions are quiet enough to be left out of captures generation.
+    let promotion_pieces: &[Piece] = match mode {
+        GenerationMode::All => &PROMOTION_PIECES,
+        GenerationMode::Captures => &PROMOTION_PIECES[..1],
+    };
 
-        let dst_square = src_square + one_square;
-        if !bits_collide(bitboard_from_square(dst_square), all_occupancies) {
-            let rank = src_square / 8;
+    for src_square in board.bitboard(Piece::Pawn, side) {
+        let targets = legality.targets(src_square);
+        let rank = src_square.rank();
 
-            if rank == back_rank {
-                moves.push(Move::encode_promotion(
-                    Piece::Pawn,
-                    src_square,
-                    dst_square,
-                    false,
-                ));
-            } else {
-                moves.push(Move::encode(Piece::Pawn, src_square, dst_square));
+        let dst_square = src_square.offset(one_square);
+        if !all_occupancies.contains(dst_square) {
+            if targets.contains(dst_square) {
+                if rank == promotion_rank {
+                    for promotion_piece in promotion_pieces {
+                        moves.push(Move::encode_promotion(
+                            Piece::Pawn,
+                            src_square,
+                            dst_square,
+                            *promotion_piece,
+                            false,
+                        ));
+                    }
+                } else if mode == GenerationMode::All {
+                    moves.push(Move::encode(Piece::Pawn, src_square, dst_square));
+                }
             }
 
             // The two squares move is only relevant if there is already no
             // blocker for the one square move.
             // We also need to make sure we are on the start rank.
-            if rank == start_rank {
-                let dst_square = src_square + two_squares;
-                if !bits_collide(bitboard_from_square(dst_square), all_occupancies) {
+            if rank == start_rank && mode == GenerationMode::All {
+                let dst_square = src_square.offset(two_squares);
+                if !all_occupancies.contains(dst_square) && targets.contains(dst_square) {
                     moves.push(Move::encode_double_push(
                         Piece::Pawn,
                         src_square,
@@ -272,142 +606,139 @@ fn generate_pawns(board: &Board, side: Side, moves: &mut Moves) {
             }
         }
 
-        let mut attacks = board.attacks.get_pawn_attacks(src_square, side);
-        if bits_collide(attacks, opp_occupancies) {
-            while attacks != 0 {
-                let dst_square = lsb_index(attacks) as i32;
-                attacks = pop_bit(attacks, dst_square);
+        let attacks = Attacks::global().get_pawn_attacks(src_square, side);
 
-                if bits_collide(bitboard_from_square(dst_square), opp_occupancies) {
-                    if src_square / 8 == back_rank {
-                        moves.push(Move::encode_promotion(
-                            Piece::Pawn,
-                            src_square,
-                            dst_square,
-                            true,
-                        ));
-                    } else {
-                        moves.push(Move::encode_capture(Piece::Pawn, src_square, dst_square));
-                    }
-                } else if en_passant_square == dst_square {
-                    // This test would be relevant only on 4th and 5th ranks, but it might
-                    // be more costly to perform a division than just test directly
-                    // with data already loaded...
-                    moves.push(Move::encode_en_passant(Piece::Pawn, src_square, dst_square));
+        for dst_square in attacks & opp_occupancies & targets {
+            if rank == promotion_rank {
+                for promotion_piece in promotion_pieces {
+                    moves.push(Move::encode_promotion(
+                        Piece::Pawn,
+                        src_square,
+                        dst_square,
+                        *promotion_piece,
+                        true,
+                    ));
                 }
+            } else {
+                moves.push(Move::encode_capture(Piece::Pawn, src_square, dst_square));
+            }
+        }
+
+        // The en-passant square is only ever set on the 3rd and 6th ranks, so the
+        // pawn attacks are enough to know whether this pawn can take.
+        // Pins and checks are handled separately for en-passant captures.
+        if let Some(en_passant_square) = board.en_passant_square {
+            if attacks.contains(en_passant_square)
+                && legality.allows_en_passant(board, side, src_square, en_passant_square)
+            {
+                moves.push(Move::encode_en_passant(
+                    Piece::Pawn,
+                    src_square,
+                    en_passant_square,
+                ));
             }
         }
     }
 }
 
 fn generate_king_castles(board: &Board, side: Side, moves: &mut Moves) {
-    let king_bitboard = board.bitboard(Piece::King, side);
-    let king_square = lsb_index(king_bitboard) as i32;
-    let opponent_side = opponent_side(side);
-
     // For some reason, we have no king...
-    if king_bitboard == 0 {
+    let Some(king_square) = board.bitboard(Piece::King, side).lsb() else {
         return;
-    }
+    };
+    let opponent_side = opponent_side(side);
 
     // The king is under attack (checked), we cannot castle.
     if is_square_attacked(board, king_square, opponent_side) {
         return;
     }
 
-    // Extract board info. Can we still castle king or queen side ?
-    let (can_castle_king_side, can_castle_queen_side) = match side {
-        Side::White => (
-            board.castling_rights & Castling::WhiteKing as u8 != 0,
-            board.castling_rights & Castling::WhiteQueen as u8 != 0,
-        ),
-        Side::Black => (
-            board.castling_rights & Castling::BlackKing as u8 != 0,
-            board.castling_rights & Castling::BlackQueen as u8 != 0,
-        ),
-        Side::Both => unreachable!(),
-    };
-
     let all_occupancies = board.occupancies[Side::Both as usize];
+    let rooks = board.bitboard(Piece::Rook, side);
+    let rank = king_square.rank();
 
-    if can_castle_king_side {
-        let squares = [king_square + 1, king_square + 2];
+    for king_side in [true, false] {
+        let right = castling_right(side, king_side);
+        if board.castling_rights & right == 0 {
+            continue;
+        }
 
-        if can_castle(board, &squares, all_occupancies, opponent_side) {
-            moves.push(Move::encode_castling(
-                Piece::King,
-                king_square,
-                king_square + 2,
-            ));
+        let rook_square = board.castling_rooks[right.trailing_zeros() as usize];
+        if !rooks.contains(rook_square) || rook_square.rank() != rank {
+            continue;
+        }
+
+        let (king_dst_square, rook_dst_square) = if king_side {
+            (
+                Square::from_rank_file(rank, 6),
+                Square::from_rank_file(rank, 5),
+            )
+        } else {
+            (
+                Square::from_rank_file(rank, 2),
+                Square::from_rank_file(rank, 3),
+            )
+        };
+
+        // Every square the king and the rook go through or to must be empty,
+        // but for their own squares: in Chess960, they can be in each other's way.
+        let span = |a: Square, b: Square| Attacks::global().get_between(a, b).with(a).with(b);
+        let path = span(king_square, king_dst_square) | span(rook_square, rook_dst_square);
+        let blockers = all_occupancies.without(king_square).without(rook_square);
+        if path.collides(blockers) {
+            continue;
         }
-    }
 
-    if can_castle_queen_side {
-        let squares = [king_square - 1, king_square - 2, king_square - 3];
+        // The king cannot go through an attacked square. The rook does not protect
+        // any of them: once it moves, it may uncover an attack along the rank.
+        let occupancy = all_occupancies.without(rook_square);
+        let king_path = Attacks::global()
+            .get_between(king_square, king_dst_square)
+            .with(king_dst_square);
+        let is_safe =
+            |square| !is_square_attacked_with_occupancy(board, square, opponent_side, occupancy);
 
-        if can_castle(board, &squares, all_occupancies, opponent_side) {
+        if king_path.into_iter().all(is_safe) {
             moves.push(Move::encode_castling(
                 Piece::King,
                 king_square,
-                king_square - 2,
+                king_dst_square,
+                rook_square,
             ));
         }
     }
 }
 
-fn can_castle(board: &Board, squares: &[i32], occupancy: u64, opponent_side: Side) -> bool {
-    let mut ok = true;
-    for square in squares {
-        let bitboard = bitboard_from_square(*square);
-        if bits_collide(bitboard, occupancy) {
-            ok = false;
-            break;
-        }
-
-        if is_square_attacked(board, *square, opponent_side) {
-            ok = false;
-            break;
-        }
-    }
-
-    ok
-}
-
 fn handle_attacks(
     piece: Piece,
-    initial_attacks: u64,
-    initial_square: i32,
-    my_occupancy: u64,
-    opponent_occupancy: u64,
+    attacks: Bitboard,
+    initial_square: Square,
+    my_occupancy: Bitboard,
+    opponent_occupancy: Bitboard,
     moves: &mut Moves,
 ) {
-    let mut attacks = initial_attacks;
-
-    while attacks != 0 {
-        let attacked_square = lsb_index(attacks) as i32;
-        attacks = pop_bit(attacks, attacked_square);
-
-        let attacked_bitboard = bitboard_from_square(attacked_square);
-
-        if bits_collide(attacked_bitboard, opponent_occupancy) {
+    for attacked_square in attacks {
+        if opponent_occupancy.contains(attacked_square) {
             moves.push(Move::encode_capture(piece, initial_square, attacked_square));
-        } else if !bits_collide(attacked_bitboard, my_occupancy) {
+        } else if !my_occupancy.contains(attacked_square) {
             moves.push(Move::encode(piece, initial_square, attacked_square));
         }
     }
 }
 
-fn generate_knights(board: &Board, side: Side, moves: &mut Moves) {
+fn generate_knights(
+    board: &Board,
+    side: Side,
+    legality: &Legality,
+    destinations: Bitboard,
+    moves: &mut Moves,
+) {
     let my_occupancy = board.occupancies[side as usize];
     let opponent_occupancy = board.occupancies[opponent_side(side) as usize];
 
-    let mut knights = board.bitboard(Piece::Knight, side);
-
-    while knights != 0 {
-        let square = lsb_index(knights) as i32;
-        knights = pop_bit(knights, square);
-
-        let attacks = board.attacks.get_knight_attacks(square);
+    for square in board.bitboard(Piece::Knight, side) {
+        let attacks =
+            Attacks::global().get_knight_attacks(square) & legality.targets(square) & destinations;
         handle_attacks(
             Piece::Knight,
             attacks,
@@ -419,18 +750,21 @@ fn generate_knights(board: &Board, side: Side, moves: &mut Moves) {
     }
 }
 
-fn generate_bishops(board: &Board, side: Side, moves: &mut Moves) {
+fn generate_bishops(
+    board: &Board,
+    side: Side,
+    legality: &Legality,
+    destinations: Bitboard,
+    moves: &mut Moves,
+) {
     let occupancy = board.occupancies[Side::Both as usize];
     let my_occupancy = board.occupancies[side as usize];
     let opponent_occupancy = board.occupancies[opponent_side(side) as usize];
 
-    let mut bishops = board.bitboard(Piece::Bishop, side);
-
-    while bishops != 0 {
-        let square = lsb_index(bishops) as i32;
-        bishops = pop_bit(bishops, square);
-
-        let attacks = board.attacks.get_bishop_attacks(square, occupancy);
+    for square in board.bitboard(Piece::Bishop, side) {
+        let attacks = Attacks::global().get_bishop_attacks(square, occupancy)
+            & legality.targets(square)
+            & destinations;
         handle_attacks(
             Piece::Bishop,
             attacks,
@@ -442,18 +776,21 @@ fn generate_bishops(board: &Board, side: Side, moves: &mut Moves) {
     }
 }
 
-fn generate_rooks(board: &Board, side: Side, moves: &mut Moves) {
+fn generate_rooks(
+    board: &Board,
+    side: Side,
+    legality: &Legality,
+    destinations: Bitboard,
+    moves: &mut Moves,
+) {
     let occupancy = board.occupancies[Side::Both as usize];
     let my_occupancy = board.occupancies[side as usize];
     let opponent_occupancy = board.occupancies[opponent_side(side) as usize];
 
-    let mut rooks = board.bitboard(Piece::Rook, side);
-
-    while rooks != 0 {
-        let square = lsb_index(rooks) as i32;
-        rooks = pop_bit(rooks, square);
-
-        let attacks = board.attacks.get_rook_attacks(square, occupancy);
+    for square in board.bitboard(Piece::Rook, side) {
+        let attacks = Attacks::global().get_rook_attacks(square, occupancy)
+            & legality.targets(square)
+            & destinations;
         handle_attacks(
             Piece::Rook,
             attacks,
@@ -465,18 +802,21 @@ fn generate_rooks(board: &Board, side: Side, moves: &mut Moves) {
     }
 }
 
-fn generate_queens(board: &Board, side: Side, moves: &mut Moves) {
+fn generate_queens(
+    board: &Board,
+    side: Side,
+    legality: &Legality,
+    destinations: Bitboard,
+    moves: &mut Moves,
+) {
     let occupancy = board.occupancies[Side::Both as usize];
     let my_occupancy = board.occupancies[side as usize];
     let opponent_occupancy = board.occupancies[opponent_side(side) as usize];
 
-    let mut queens = board.bitboard(Piece::Queen, side);
-
-    while queens != 0 {
-        let square = lsb_index(queens) as i32;
-        queens = pop_bit(queens, square);
-
-        let attacks = board.attacks.get_queen_attacks(square, occupancy);
+    for square in board.bitboard(Piece::Queen, side) {
+        let attacks = Attacks::global().get_queen_attacks(square, occupancy)
+            & legality.targets(square)
+            & destinations;
         handle_attacks(
             Piece::Queen,
             attacks,
@@ -488,28 +828,164 @@ fn generate_queens(board: &Board, side: Side, moves: &mut Moves) {
     }
 }
 
-fn generate_kings(board: &Board, side: Side, moves: &mut Moves) {
+fn generate_kings(board: &Board, side: Side, destinations: Bitboard, moves: &mut Moves) {
     let my_occupancy = board.occupancies[side as usize];
     let opponent_occupancy = board.occupancies[opponent_side(side) as usize];
 
     let king = board.bitboard(Piece::King, side);
-    let square = lsb_index(king) as i32;
-
-    let mut attacks = board.attacks.get_king_attacks(square);
-
-    while attacks != 0 {
-        let attacked_square = lsb_index(attacks) as i32;
-        attacks = pop_bit(attacks, attacked_square);
+    let Some(square) = king.lsb() else {
+        return;
+    };
 
-        let attacked_bitboard = bitboard_from_square(attacked_square);
+    // Sliders attacking the king also attack the squares behind it, so the king
+    // must not be considered as a blocker when checking its destination squares.
+    let occupancy = board.occupancies[Side::Both as usize] & !king;
 
+    for attacked_square in Attacks::global().get_king_attacks(square) & destinations {
         // We need to make sure we do not put ourselves in check
-        if !is_square_attacked(board, attacked_square, opponent_side(side)) {
-            if bits_collide(attacked_bitboard, opponent_occupancy) {
+        if !is_square_attacked_with_occupancy(
+            board,
+            attacked_square,
+            opponent_side(side),
+            occupancy,
+        ) {
+            if opponent_occupancy.contains(attacked_square) {
                 moves.push(Move::encode_capture(Piece::King, square, attacked_square));
-            } else if !bits_collide(attacked_bitboard, my_occupancy) {
+            } else if !my_occupancy.contains(attacked_square) {
                 moves.push(Move::encode(Piece::King, square, attacked_square));
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::squares::a8;
+
+    #[test]
+    fn promotions_to_every_piece_are_generated() {
+        let board = Board::from_fen("r1r1k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+        let moves = generate_moves(&board);
+        let mut promotions: Vec<String> = moves
+            .moves()
+            .iter()
+            .filter(|mv| mv.is_promotion())
+            .map(|mv| mv.to_uci())
+            .collect();
+        promotions.sort();
+
+        let mut expected = Vec::new();
+        for dst in ["a8", "b8", "c8"] {
+            for piece in ["b", "n", "q", "r"] {
+                expected.push(format!("b7{}{}", dst, piece));
+            }
+        }
+
+        assert_eq!(promotions, expected);
+
+        // The promoted piece is the one that lands on the board.
+        let knight_promotion = *moves
+            .moves()
+            .iter()
+            .find(|mv| mv.to_uci() == "b7a8n")
+            .unwrap();
+        assert_eq!(knight_promotion.promotion_piece(), Some(Piece::Knight));
+
+        let mut after = board;
+        after.play_move(knight_promotion);
+        assert_eq!(after.piece_on(a8, Side::White), Some(Piece::Knight));
+        assert_eq!(after.piece_on(a8, Side::Black), None);
+        assert!(after.bitboard(Piece::Pawn, Side::White).is_empty());
+    }
+
+    #[test]
+    fn captures_generation_keeps_captures_and_queen_promotions() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N w - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen(fen).unwrap();
+            let uci = |moves: Vec<Move>| {
+                let mut moves: Vec<String> = moves.iter().map(|mv| mv.to_uci()).collect();
+                moves.sort();
+                moves
+            };
+
+            let expected = generate_moves(&board)
+                .moves()
+                .iter()
+                .filter(|mv| match mv.promotion_piece() {
+                    Some(piece) => piece == Piece::Queen,
+                    None => mv.is_capture() || mv.is_en_passant(),
+                })
+                .copied()
+                .collect();
+
+            assert_eq!(
+                uci(generate_captures(&board).moves().to_vec()),
+                uci(expected),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    /// Legal moves of the position, found by playing the pseudo-legal ones.
+    fn filter_pseudo_legal_moves(board: &Board) -> Vec<u32> {
+        let mut legal_moves: Vec<u32> = generate_pseudo_legal_moves(board)
+            .moves()
+            .iter()
+            .filter(|mv| {
+                let mut after = *board;
+                after.play_move(**mv);
+                !is_in_check(&after, board.side_to_move)
+            })
+            .map(|mv| mv.to_bits())
+            .collect();
+        legal_moves.sort();
+        legal_moves
+    }
+
+    #[test]
+    fn filtered_pseudo_legal_moves_are_the_legal_moves() {
+        let suite = include_str!("../res/perftsuite.epd");
+        let fens = suite
+            .lines()
+            .map(|line| line.split(';').next().unwrap().trim())
+            .filter(|fen| !fen.is_empty() && !fen.starts_with('#'));
+
+        for fen in fens {
+            let board = Board::from_fen(fen).unwrap();
+
+            // The suite positions and the ones reached from them.
+            let mut boards = vec![board];
+            for mv in generate_moves(&board).moves() {
+                let mut child = board;
+                child.play_move(*mv);
+                boards.push(child);
+            }
+
+            for board in boards {
+                let mut legal_moves: Vec<u32> = generate_moves(&board)
+                    .moves()
+                    .iter()
+                    .map(|mv| mv.to_bits())
+                    .collect();
+                legal_moves.sort();
+
+                assert_eq!(
+                    filter_pseudo_legal_moves(&board),
+                    legal_moves,
+                    "{}",
+                    board.to_fen()
+                );
+            }
+        }
+    }
+}
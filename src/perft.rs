@@ -0,0 +1,317 @@
+use std::time::{Duration, Instant};
+
+use crate::attacks::{Attacks, SliderLayout};
+use crate::board::*;
+use crate::eval::Evaluation;
+use crate::move_generator::*;
+
+/// Counts the leaf nodes of the legal move tree, down to the given depth.
+pub fn perft(board: &Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = generate_moves(board);
+
+    // Bulk counting: the moves are legal, so there is no need to play
+    // the last ply to count them. Debug builds still play it, to check
+    // the board after every move.
+    if depth == 1 && !cfg!(debug_assertions) {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0u64;
+    let mut my_board = *board;
+
+    for mv in moves.moves() {
+        let old_board = my_board.play_move(*mv);
+        debug_assert_incremental_state(&my_board, *mv);
+        nodes += perft(&my_board, depth - 1);
+        my_board = old_board;
+    }
+
+    nodes
+}
+
+/// Same as `perft`, but walks the tree with `make_move`/`unmake_move`
+/// on a single board instead of copying it at each node.
+pub fn perft_unmake(board: &mut Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = generate_moves(board);
+    if depth == 1 && !cfg!(debug_assertions) {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0u64;
+
+    for mv in moves.moves() {
+        let hash = board.hash;
+        let undo = board.make_move(*mv);
+        debug_assert_incremental_state(board, *mv);
+        nodes += perft_unmake(board, depth - 1);
+        board.unmake_move(undo);
+        debug_assert_eq!(board.hash, hash, "Hash not restored after unmaking {}", mv);
+    }
+
+    nodes
+}
+
+/// Checks the incrementally updated parts of the board against
+/// a computation from scratch, in debug builds only.
+fn debug_assert_incremental_state(board: &Board, mv: Move) {
+    debug_assert_eq!(
+        board.hash,
+        board.compute_hash(),
+        "Incremental hash mismatch after {}",
+        mv
+    );
+    debug_assert_eq!(
+        board.evaluation,
+        Evaluation::new(&board.pieces),
+        "Incremental evaluation mismatch after {}",
+        mv
+    );
+    debug_assert!(
+        board.mailbox_matches_bitboards(),
+        "Mailbox out of sync after {}",
+        mv
+    );
+}
+
+/// Same as `perft`, but prints the node count of each root move.
+/// This is the usual way to locate a move generation bug, by comparing
+/// the output with a reference engine.
+pub fn divide(board: &Board, depth: u32) -> u64 {
+    let mut nodes = 0u64;
+    let mut my_board = *board;
+
+    let start_time = Instant::now();
+    let castling_notation = board.castling_notation();
+
+    for mv in generate_moves(board).moves() {
+        let old_board = my_board.play_move(*mv);
+        let move_nodes = if depth > 1 {
+            perft(&my_board, depth - 1)
+        } else {
+            1
+        };
+        my_board = old_board;
+
+        println!("{}: {}", mv.to_uci_with(castling_notation), move_nodes);
+        nodes += move_nodes;
+    }
+
+    let dt = start_time.elapsed();
+
+    println!("\nNodes searched: {}", nodes);
+    println!(
+        "Time: {}ms ({:.0} nps)",
+        dt.as_millis(),
+        nodes as f64 / dt.as_secs_f64()
+    );
+
+    nodes
+}
+
+/// Positions of the perft benchmark (the usual ones from the Chess Programming Wiki),
+/// along with the depth they are searched to and the expected node count.
+const BENCH_POSITIONS: [(&str, u32, u64); 6] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        6,
+        119_060_324,
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        5,
+        193_690_690,
+    ),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 6, 11_030_083),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        5,
+        15_833_292,
+    ),
+    (
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        5,
+        89_941_194,
+    ),
+    (
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        5,
+        164_075_551,
+    ),
+];
+
+/// How perft walks the move tree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MakeMode {
+    /// Each node works on its own copy of the board (`perft`).
+    #[default]
+    CopyMake,
+
+    /// A single board is updated and restored in place (`perft_unmake`).
+    UnmakeMove,
+}
+
+/// Slider table layouts compared by the benchmark.
+const BENCH_LAYOUTS: [SliderLayout; 2] = [SliderLayout::Packed, SliderLayout::Padded];
+
+/// Runs perft on a fixed set of positions and prints the speed of the move generator,
+/// once with each slider table layout. Returns the total node count of a single layout.
+pub fn bench(mode: MakeMode) -> u64 {
+    let attacks = *Attacks::global();
+    let mut total_nodes = 0u64;
+    let mut total_times = [Duration::ZERO; BENCH_LAYOUTS.len()];
+
+    println!(
+        "{:>11}       {:>25}  {:>25}",
+        "", "packed layout", "padded layout"
+    );
+
+    for (fen, depth, expected) in BENCH_POSITIONS {
+        let mut nodes = [0u64; BENCH_LAYOUTS.len()];
+        let mut times = [Duration::ZERO; BENCH_LAYOUTS.len()];
+
+        for (index, layout) in BENCH_LAYOUTS.into_iter().enumerate() {
+            attacks.with_layout(layout).set_global();
+            let mut board = Board::from_fen(fen).unwrap();
+
+            let position_time = Instant::now();
+            nodes[index] = match mode {
+                MakeMode::CopyMake => perft(&board, depth),
+                MakeMode::UnmakeMove => perft_unmake(&mut board, depth),
+            };
+            times[index] = position_time.elapsed();
+            total_times[index] += times[index];
+        }
+
+        let timings: Vec<String> = times.iter().map(|dt| format_speed(nodes[0], *dt)).collect();
+        println!(
+            "{:>11} nodes {}  {}{}",
+            nodes[0],
+            timings.join("  "),
+            fen,
+            if nodes.iter().all(|nodes| *nodes == expected) {
+                ""
+            } else {
+                "  (MISMATCH)"
+            }
+        );
+        total_nodes += nodes[0];
+    }
+
+    attacks.set_global();
+
+    let timings: Vec<String> = total_times
+        .iter()
+        .map(|dt| format_speed(total_nodes, *dt))
+        .collect();
+    println!("\n{:>11} nodes {}", total_nodes, timings.join("  "));
+
+    total_nodes
+}
+
+/// Time and speed of a perft run, e.g. "  1939ms     84611962 nps".
+fn format_speed(nodes: u64, dt: Duration) -> String {
+    format!(
+        "{:>6}ms {:>12.0} nps",
+        dt.as_millis(),
+        nodes as f64 / dt.as_secs_f64()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERFT_SUITE: &str = include_str!("../res/perftsuite.epd");
+
+    /// A position from a perft suite, along with its expected node counts.
+    struct PerftPosition {
+        fen: String,
+
+        /// (depth, expected nodes) pairs
+        expected: Vec<(u32, u64)>,
+    }
+
+    /// Parses an EPD-style perft suite, where each line looks like
+    /// `<fen> ;D1 20 ;D2 400 ;D3 8902`.
+    /// Empty lines and lines starting with '#' are ignored.
+    fn parse_perft_suite(content: &str) -> Vec<PerftPosition> {
+        let mut positions = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split(';');
+            let fen = fields.next().unwrap_or("").trim().to_string();
+
+            let expected = fields
+                .filter_map(|field| {
+                    let (depth, nodes) = field.trim().split_once(' ')?;
+                    let depth = depth.strip_prefix('D')?.parse().ok()?;
+                    let nodes = nodes.trim().parse().ok()?;
+                    Some((depth, nodes))
+                })
+                .collect();
+
+            positions.push(PerftPosition { fen, expected });
+        }
+
+        positions
+    }
+
+    fn run_suite(max_depth: u32) {
+        let mut failures = Vec::new();
+
+        for position in parse_perft_suite(PERFT_SUITE) {
+            let board = Board::from_fen(&position.fen).unwrap();
+
+            for (depth, expected) in position.expected {
+                if depth > max_depth {
+                    continue;
+                }
+
+                let nodes = perft(&board, depth);
+                if nodes != expected {
+                    failures.push(format!(
+                        "{} (depth {}): expected {}, got {}",
+                        position.fen, depth, expected, nodes
+                    ));
+                }
+
+                let mut unmake_board = board;
+                let nodes = perft_unmake(&mut unmake_board, depth);
+                if nodes != expected {
+                    failures.push(format!(
+                        "{} (depth {}, unmake): expected {}, got {}",
+                        position.fen, depth, expected, nodes
+                    ));
+                }
+            }
+        }
+
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn perft_suite_shallow() {
+        run_suite(3);
+    }
+
+    /// The full suite takes a while, run it in release mode with
+    /// `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn perft_suite_full() {
+        run_suite(u32::MAX);
+    }
+}
@@ -0,0 +1,747 @@
+//! Portable Game Notation: reading and writing games, with their comments,
+//! annotations and variations.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::board::*;
+use crate::fens;
+use crate::move_generator::Move;
+use crate::san::{move_to_san, parse_san, SanError, SanMode};
+
+/// Tags every exported game starts with, in this order.
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// Exported movetext lines are at most this long.
+const MAX_LINE_LENGTH: usize = 80;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// Game still in progress, abandoned or unknown result ("*")
+    #[default]
+    Unknown,
+}
+
+impl GameResult {
+    pub fn from_pgn(result: &str) -> Option<Self> {
+        match result {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn to_pgn(self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        }
+    }
+}
+
+/// A move of a game, along with its annotations and the alternatives to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameMove {
+    pub mv: Move,
+
+    /// Numeric annotation glyphs ($1 is "!", $2 is "?", and so on).
+    pub nags: Vec<u8>,
+
+    /// Comment following the move.
+    pub comment: Option<String>,
+
+    /// Lines which could have been played instead of this move.
+    pub variations: Vec<Line>,
+}
+
+impl GameMove {
+    pub fn new(mv: Move) -> Self {
+        Self {
+            mv,
+            nags: Vec::new(),
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+/// A sequence of moves: the main line of a game, or one of its variations.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Line {
+    /// Comment preceding the first move.
+    pub comment: Option<String>,
+    pub moves: Vec<GameMove>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    /// Tag pairs, in their original order.
+    pub tags: Vec<(String, String)>,
+
+    /// Position before the first move, given by the FEN tag if any.
+    pub starting_position: Board,
+
+    pub mainline: Line,
+    pub result: GameResult,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    /// Empty game from the standard starting position.
+    pub fn new() -> Self {
+        Self {
+            tags: Vec::new(),
+            starting_position: Board::from_fen(fens::STARTING_BOARD_FEN).unwrap(),
+            mainline: Line::default(),
+            result: GameResult::Unknown,
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some((_, tag_value)) => *tag_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Position reached at the end of the main line.
+    pub fn final_position(&self) -> Board {
+        let mut board = self.starting_position;
+        for game_move in &self.mainline.moves {
+            board.play_move(game_move.mv);
+        }
+        board
+    }
+
+    /// Exports the game, seven tag roster first, with lines of at most 80 characters.
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+
+        for name in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => self.result.to_pgn(),
+                "Date" => self.tag(name).unwrap_or("????.??.??"),
+                _ => self.tag(name).unwrap_or("?"),
+            };
+            write_tag(&mut pgn, name, value);
+        }
+
+        let standard_start = self.starting_position.to_fen() == fens::STARTING_BOARD_FEN;
+        if !standard_start && self.tag("FEN").is_none() {
+            write_tag(&mut pgn, "SetUp", "1");
+            write_tag(&mut pgn, "FEN", &self.starting_position.to_fen());
+        }
+
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.contains(&name.as_str()) {
+                write_tag(&mut pgn, name, value);
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        movetext_tokens(&self.starting_position, &self.mainline, &mut tokens);
+        tokens.push(self.result.to_pgn().to_string());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+            pgn.push_str(&token);
+            line_length += token.len();
+        }
+        pgn.push('\n');
+
+        pgn
+    }
+}
+
+fn write_tag(pgn: &mut String, name: &str, value: &str) {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+}
+
+/// Comments are split into words, so that lines can be wrapped inside them.
+fn comment_tokens(comment: &str, tokens: &mut Vec<String>) {
+    let mut words: Vec<String> = comment
+        .split_whitespace()
+        .map(|word| word.replace('}', ""))
+        .collect();
+    if words.is_empty() {
+        words.push(String::new());
+    }
+
+    words[0].insert(0, '{');
+    words.last_mut().unwrap().push('}');
+    tokens.extend(words);
+}
+
+fn movetext_tokens(board: &Board, line: &Line, tokens: &mut Vec<String>) {
+    let mut board = *board;
+
+    // Black moves need their number at the start of a line, and after anything
+    // interrupting the moves.
+    let mut needs_number = true;
+
+    if let Some(comment) = &line.comment {
+        comment_tokens(comment, tokens);
+    }
+
+    for game_move in &line.moves {
+        match board.side_to_move {
+            Side::White => tokens.push(format!("{}.", board.fullmove_number)),
+            _ if needs_number => tokens.push(format!("{}...", board.fullmove_number)),
+            _ => {}
+        }
+        needs_number = false;
+
+        tokens.push(move_to_san(&board, game_move.mv));
+        tokens.extend(game_move.nags.iter().map(|nag| format!("${}", nag)));
+
+        if let Some(comment) = &game_move.comment {
+            comment_tokens(comment, tokens);
+            needs_number = true;
+        }
+
+        for variation in &game_move.variations {
+            let first = tokens.len();
+            movetext_tokens(&board, variation, tokens);
+            if tokens.len() > first {
+                tokens[first].insert(0, '(');
+                tokens.last_mut().unwrap().push(')');
+            }
+            needs_number = true;
+        }
+
+        board.play_move(game_move.mv);
+    }
+}
+
+/// What went wrong while reading a game.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnErrorKind {
+    UnexpectedCharacter(char),
+    UnexpectedToken(String),
+    UnterminatedComment,
+    UnterminatedTag,
+    /// A variation is not closed before the end of the game
+    UnterminatedVariation,
+    /// A variation, or an annotation, does not follow any move
+    MissingMove,
+    InvalidFen(FenError),
+    InvalidMove(SanError),
+}
+
+/// Error while reading a game, with the position (starting at 1) it occurred at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: PgnErrorKind,
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+
+        match &self.kind {
+            PgnErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            PgnErrorKind::UnexpectedToken(token) => write!(f, "unexpected '{}'", token),
+            PgnErrorKind::UnterminatedComment => write!(f, "unterminated comment"),
+            PgnErrorKind::UnterminatedTag => write!(f, "unterminated tag pair"),
+            PgnErrorKind::UnterminatedVariation => write!(f, "unterminated variation"),
+            PgnErrorKind::MissingMove => write!(f, "no move to attach this to"),
+            PgnErrorKind::InvalidFen(error) => write!(f, "invalid FEN tag: {}", error),
+            PgnErrorKind::InvalidMove(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    MoveNumber,
+    San(String),
+    Nag(u8),
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Tag(name, value) => write!(f, "[{} \"{}\"]", name, value),
+            Token::MoveNumber => write!(f, "move number"),
+            Token::San(san) => write!(f, "{}", san),
+            Token::Nag(nag) => write!(f, "${}", nag),
+            Token::Comment(comment) => write!(f, "{{{}}}", comment),
+            Token::VariationStart => write!(f, "("),
+            Token::VariationEnd => write!(f, ")"),
+            Token::Result(result) => write!(f, "{}", result.to_pgn()),
+        }
+    }
+}
+
+/// Line and column in the PGN text, both starting at 1.
+#[derive(Debug, Clone, Copy)]
+struct TextPosition {
+    line: usize,
+    column: usize,
+}
+
+impl TextPosition {
+    fn error(self, kind: PgnErrorKind) -> PgnError {
+        PgnError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    position: TextPosition,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(text: &'a str, first_line: usize) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+            position: TextPosition {
+                line: first_line,
+                column: 1,
+            },
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut result = String::new();
+        while let Some(c) = self.chars.peek().copied().filter(|c| predicate(*c)) {
+            result.push(c);
+            self.next_char();
+        }
+        result
+    }
+
+    fn is_symbol_char(c: char) -> bool {
+        c.is_ascii_alphanumeric() || "_+#=:-/".contains(c)
+    }
+
+    fn next_token(&mut self) -> Result<Option<(Token, TextPosition)>, PgnError> {
+        loop {
+            self.take_while(char::is_whitespace);
+
+            let start = self.position;
+            let Some(c) = self.next_char() else {
+                return Ok(None);
+            };
+
+            let token = match c {
+                // Escape mechanism, the rest of the line is ignored.
+                '%' if start.column == 1 => {
+                    self.take_while(|c| c != '\n');
+                    continue;
+                }
+                ';' => Token::Comment(self.take_while(|c| c != '\n').trim().to_string()),
+                '{' => {
+                    let comment = self.take_while(|c| c != '}');
+                    if self.next_char().is_none() {
+                        return Err(start.error(PgnErrorKind::UnterminatedComment));
+                    }
+                    Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" "))
+                }
+                '[' => self.tag(start)?,
+                '(' => Token::VariationStart,
+                ')' => Token::VariationEnd,
+                '*' => Token::Result(GameResult::Unknown),
+                '$' => {
+                    let nag = self.take_while(|c| c.is_ascii_digit());
+                    Token::Nag(nag.parse().map_err(|_| {
+                        start.error(PgnErrorKind::UnexpectedToken(format!("${}", nag)))
+                    })?)
+                }
+                '!' | '?' => {
+                    let glyph = format!("{}{}", c, self.take_while(|c| c == '!' || c == '?'));
+                    let nag = match glyph.as_str() {
+                        "!" => 1,
+                        "?" => 2,
+                        "!!" => 3,
+                        "??" => 4,
+                        "!?" => 5,
+                        "?!" => 6,
+                        _ => return Err(start.error(PgnErrorKind::UnexpectedToken(glyph))),
+                    };
+                    Token::Nag(nag)
+                }
+                c if Self::is_symbol_char(c) => {
+                    let symbol = format!("{}{}", c, self.take_while(Self::is_symbol_char));
+
+                    if symbol.chars().all(|c| c.is_ascii_digit()) {
+                        self.take_while(|c| c == '.');
+                        Token::MoveNumber
+                    } else if let Some(result) = GameResult::from_pgn(&symbol) {
+                        Token::Result(result)
+                    } else {
+                        Token::San(symbol)
+                    }
+                }
+                c => return Err(start.error(PgnErrorKind::UnexpectedCharacter(c))),
+            };
+
+            return Ok(Some((token, start)));
+        }
+    }
+
+    /// Tag pair, such as `[White "Carlsen, Magnus"]`, its opening bracket excluded.
+    fn tag(&mut self, start: TextPosition) -> Result<Token, PgnError> {
+        let unterminated = || start.error(PgnErrorKind::UnterminatedTag);
+
+        self.take_while(char::is_whitespace);
+        let name = self.take_while(Self::is_symbol_char);
+        self.take_while(char::is_whitespace);
+
+        if name.is_empty() || self.next_char() != Some('"') {
+            return Err(unterminated());
+        }
+
+        let mut value = String::new();
+        loop {
+            match self.next_char().ok_or_else(unterminated)? {
+                '"' => break,
+                '\\' => value.push(self.next_char().ok_or_else(unterminated)?),
+                c => value.push(c),
+            }
+        }
+
+        self.take_while(char::is_whitespace);
+        if self.next_char() != Some(']') {
+            return Err(unterminated());
+        }
+
+        Ok(Token::Tag(name, value))
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<(Token, TextPosition)>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Result<Option<&(Token, TextPosition)>, PgnError> {
+        if self.peeked.is_none() {
+            self.peeked = self.lexer.next_token()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next(&mut self) -> Result<Option<(Token, TextPosition)>, PgnError> {
+        match self.peeked.take() {
+            Some(token) => Ok(Some(token)),
+            None => self.lexer.next_token(),
+        }
+    }
+
+    fn game(&mut self) -> Result<Game, PgnError> {
+        let mut game = Game::new();
+
+        while let Some((Token::Tag(_, _), _)) = self.peek()? {
+            let Some((Token::Tag(name, value), position)) = self.next()? else {
+                unreachable!()
+            };
+
+            if name == "FEN" {
+                game.starting_position = Board::from_fen(&value)
+                    .map_err(|error| position.error(PgnErrorKind::InvalidFen(error)))?;
+            }
+            game.tags.push((name, value));
+        }
+
+        game.result = game
+            .tag("Result")
+            .and_then(GameResult::from_pgn)
+            .unwrap_or_default();
+
+        let (mainline, result) = self.line(&game.starting_position, None)?;
+        game.mainline = mainline;
+        if let Some(result) = result {
+            game.result = result;
+        }
+
+        if let Some((token, position)) = self.next()? {
+            return Err(position.error(PgnErrorKind::UnexpectedToken(token.to_string())));
+        }
+
+        Ok(game)
+    }
+
+    /// Reads moves from the given position, up to the end of the variation when
+    /// `variation_start` is given, or up to the result of the game otherwise.
+    fn line(
+        &mut self,
+        board: &Board,
+        variation_start: Option<TextPosition>,
+    ) -> Result<(Line, Option<GameResult>), PgnError> {
+        let mut line = Line::default();
+        let mut board = *board;
+        let mut previous_board = board;
+
+        loop {
+            let Some((token, position)) = self.next()? else {
+                return match variation_start {
+                    Some(start) => Err(start.error(PgnErrorKind::UnterminatedVariation)),
+                    None => Ok((line, None)),
+                };
+            };
+
+            match token {
+                Token::MoveNumber => {}
+                Token::San(san) => {
+                    let mv = parse_san(&board, &san, SanMode::Lenient)
+                        .map_err(|error| position.error(PgnErrorKind::InvalidMove(error)))?;
+
+                    previous_board = board;
+                    board.play_move(mv);
+                    line.moves.push(GameMove::new(mv));
+                }
+                Token::Nag(nag) => line
+                    .moves
+                    .last_mut()
+                    .ok_or_else(|| position.error(PgnErrorKind::MissingMove))?
+                    .nags
+                    .push(nag),
+                Token::Comment(comment) => {
+                    let target = match line.moves.last_mut() {
+                        Some(game_move) => &mut game_move.comment,
+                        None => &mut line.comment,
+                    };
+                    *target = Some(match target.take() {
+                        Some(previous) => format!("{} {}", previous, comment),
+                        None => comment,
+                    });
+                }
+                Token::VariationStart => {
+                    if line.moves.is_empty() {
+                        return Err(position.error(PgnErrorKind::MissingMove));
+                    }
+                    let (variation, _) = self.line(&previous_board, Some(position))?;
+                    line.moves.last_mut().unwrap().variations.push(variation);
+                }
+                Token::VariationEnd if variation_start.is_some() => return Ok((line, None)),
+                Token::Result(result) if variation_start.is_none() => {
+                    return Ok((line, Some(result)))
+                }
+                Token::Result(_) => {
+                    let start = variation_start.unwrap();
+                    return Err(start.error(PgnErrorKind::UnterminatedVariation));
+                }
+                token => {
+                    return Err(position.error(PgnErrorKind::UnexpectedToken(token.to_string())))
+                }
+            }
+        }
+    }
+}
+
+/// Splits a PGN database into games, along with the line each one starts at.
+/// A game ends where the tag pairs of the next one start, so that reading can
+/// go on past a malformed game.
+fn split_games(pgn: &str) -> Vec<(usize, String)> {
+    let mut games: Vec<(usize, String)> = Vec::new();
+    let mut in_movetext = false;
+
+    for (index, line) in pgn.lines().enumerate() {
+        let trimmed = line.trim_start();
+
+        if games.is_empty() || (in_movetext && trimmed.starts_with('[')) {
+            games.push((index + 1, String::new()));
+            in_movetext = false;
+        }
+        if !trimmed.is_empty() && !trimmed.starts_with('[') && !trimmed.starts_with('%') {
+            in_movetext = true;
+        }
+
+        let text = &mut games.last_mut().unwrap().1;
+        text.push_str(line);
+        text.push('\n');
+    }
+
+    games.retain(|(_, text)| !text.trim().is_empty());
+    games
+}
+
+/// Reads every game of a PGN database. A malformed game yields an error,
+/// and does not prevent reading the following ones.
+pub fn read_games(pgn: &str) -> Vec<Result<Game, PgnError>> {
+    split_games(pgn)
+        .into_iter()
+        .map(|(first_line, text)| {
+            Parser {
+                lexer: Lexer::new(&text, first_line),
+                peeked: None,
+            }
+            .game()
+        })
+        .collect()
+}
+
+/// Reads the first game of a PGN text.
+pub fn read_game(pgn: &str) -> Option<Result<Game, PgnError>> {
+    read_games(pgn).into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &str = r#"[Event "Casual game"]
+[Site "London"]
+[Date "1851.06.21"]
+[Round "?"]
+[White "Anderssen, Adolf"]
+[Black "Kieseritzky, Lionel"]
+[Result "1-0"]
+[ECO "C33"]
+
+1. e4 e5 2. f4 exf4 3. Bc4 Qh4+ 4. Kf1 b5 $1 {Bryan's counter gambit} 5. Bxb5
+Nf6 6. Nf3 Qh6 7. d3 Nh5 8. Nh4 Qg5 9. Nf5 c6 10. g4 Nf6 11. Rg1! cxb5 12. h4
+Qg6 13. h5 Qg5 14. Qf3 Ng8 15. Bxf4 Qf6 16. Nc3 Bc5 (16... Bb7 17. Nd5) 17.
+Nd5 Qxb2 18. Bd6 Bxg1 (18... Qxa1+ 19. Ke2 Qb2 (19... Bxg1) 20. Kd2) 19. e5
+Qxa1+ 20. Ke2 Na6 21. Nxg7+ Kd8 22. Qf6+ Nxf6 23. Be7# 1-0
+"#;
+
+    #[test]
+    fn games_are_read() {
+        let game = read_game(GAME).unwrap().unwrap();
+
+        assert_eq!(game.tag("White"), Some("Anderssen, Adolf"));
+        assert_eq!(game.tag("ECO"), Some("C33"));
+        assert_eq!(game.result, GameResult::WhiteWins);
+        assert_eq!(game.mainline.moves.len(), 45);
+
+        let b5 = &game.mainline.moves[7];
+        assert_eq!(b5.mv.to_uci(), "b7b5");
+        assert_eq!(b5.nags, vec![1]);
+        assert_eq!(b5.comment.as_deref(), Some("Bryan's counter gambit"));
+        assert_eq!(game.mainline.moves[20].nags, vec![1]);
+
+        let bxg1 = &game.mainline.moves[35];
+        assert_eq!(bxg1.variations.len(), 1);
+        assert_eq!(bxg1.variations[0].moves[2].variations.len(), 1);
+
+        let final_position = game.final_position();
+        assert_eq!(
+            final_position.to_fen(),
+            "r1bk3r/p2pBpNp/n4n2/1p1NP2P/6P1/3P4/P1P1K3/q5b1 b - - 1 23"
+        );
+    }
+
+    #[test]
+    fn games_are_written_back() {
+        let game = read_game(GAME).unwrap().unwrap();
+        let pgn = game.to_pgn();
+
+        assert!(pgn.starts_with(
+            "[Event \"Casual game\"]\n[Site \"London\"]\n[Date \"1851.06.21\"]\n\
+             [Round \"?\"]\n[White \"Anderssen, Adolf\"]\n[Black \"Kieseritzky, Lionel\"]\n\
+             [Result \"1-0\"]\n[ECO \"C33\"]\n\n1. e4 e5 2. f4 exf4 3. Bc4 Qh4+ 4. Kf1 b5 $1 \
+             {Bryan's counter gambit} 5. Bxb5"
+        ));
+        assert!(pgn.contains("16. Nc3 Bc5 (16... Bb7 17. Nd5) 17. Nd5"));
+        assert!(pgn.contains("(18... Qxa1+ 19. Ke2 Qb2 (19... Bxg1) 20. Kd2) 19. e5"));
+        assert!(pgn.ends_with("23. Be7# 1-0\n"));
+        assert!(pgn.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+
+        let reread = read_game(&pgn).unwrap().unwrap();
+        assert_eq!(reread.tags, game.tags);
+        assert_eq!(reread.mainline, game.mainline);
+        assert_eq!(reread.to_pgn(), pgn);
+    }
+
+    #[test]
+    fn seven_tag_roster_and_fen_are_filled_in() {
+        let mut game = Game::new();
+        game.starting_position = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").unwrap();
+        let mv = parse_san(&game.starting_position, "Kd7", SanMode::Strict).unwrap();
+        game.mainline.moves.push(GameMove::new(mv));
+
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"?\"]\n[Black \"?\"]\n[Result \"*\"]\n[SetUp \"1\"]\n\
+             [FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n\n40... Kd7 *\n"
+        );
+    }
+
+    #[test]
+    fn malformed_games_report_their_position() {
+        let pgn = "[Event \"First\"]\n\n1. e4 e5 2. Ke3 Nc6 *\n\n\
+                   [Event \"Second\"]\n\n1. d4 (1. e4 d5 *\n\n\
+                   [Event \"Third\"]\n\n1. d4 {unterminated *\n\n\
+                   [Event \"Fourth\"]\n\n1. c4 c5 1/2-1/2\n";
+        let games = read_games(pgn);
+
+        assert_eq!(games.len(), 4);
+        assert_eq!(
+            games[0].as_ref().unwrap_err(),
+            &PgnError {
+                line: 3,
+                column: 13,
+                kind: PgnErrorKind::InvalidMove(SanError::IllegalMove("Ke3".to_string())),
+            }
+        );
+        assert_eq!(
+            games[1].as_ref().unwrap_err(),
+            &PgnError {
+                line: 7,
+                column: 7,
+                kind: PgnErrorKind::UnterminatedVariation,
+            }
+        );
+        assert_eq!(
+            games[2].as_ref().unwrap_err().kind,
+            PgnErrorKind::UnterminatedComment
+        );
+        assert_eq!(games[3].as_ref().unwrap().result, GameResult::Draw);
+    }
+}
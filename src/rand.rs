@@ -1,4 +1,4 @@
-fn get_random_u32_number(random_state: u32) -> u32 {
+const fn get_random_u32_number(random_state: u32) -> u32 {
     let mut number = random_state;
 
     // xorshift32
@@ -9,7 +9,7 @@ fn get_random_u32_number(random_state: u32) -> u32 {
     number
 }
 
-fn get_random_u64_number(random_state: u32) -> (u64, u32) {
+pub const fn get_random_u64_number(random_state: u32) -> (u64, u32) {
     let r1 = get_random_u32_number(random_state);
     let r2 = get_random_u32_number(r1);
     let r3 = get_random_u32_number(r2);
@@ -0,0 +1,407 @@
+//! Standard Algebraic Notation (e.g. "Nbd7", "exd5", "e8=Q+", "O-O-O#").
+
+use crate::board::*;
+use crate::move_generator::*;
+use crate::squares::Square;
+
+/// How forgiving `parse_san` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SanMode {
+    /// Only the exact SAN of the move is accepted, check and mate markers included.
+    Strict,
+
+    /// Also accepts the usual variations found in the wild: "0-0" castles,
+    /// promotions without '=' ("e8Q", "e8q"), missing or wrong check markers,
+    /// annotations ("!", "?!"), missing 'x' and superfluous disambiguation.
+    Lenient,
+}
+
+/// Reasons for which a SAN string can be rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    /// The string is not algebraic notation
+    Invalid(String),
+    /// No legal move matches
+    IllegalMove(String),
+    /// Several legal moves match
+    AmbiguousMove(String),
+    /// Strict mode only: the move is legal, but not written in canonical SAN
+    NotCanonical { san: String, expected: String },
+}
+
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanError::Invalid(san) => write!(f, "invalid SAN '{}'", san),
+            SanError::IllegalMove(san) => write!(f, "illegal move '{}'", san),
+            SanError::AmbiguousMove(san) => write!(f, "ambiguous move '{}'", san),
+            SanError::NotCanonical { san, expected } => {
+                write!(f, "'{}' should be written '{}'", san, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+fn piece_letter(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        Piece::King => 'K',
+    }
+}
+
+fn piece_from_letter(letter: char) -> Option<Piece> {
+    match letter {
+        'N' => Some(Piece::Knight),
+        'B' => Some(Piece::Bishop),
+        'R' => Some(Piece::Rook),
+        'Q' => Some(Piece::Queen),
+        'K' => Some(Piece::King),
+        _ => None,
+    }
+}
+
+/// SAN of a legal move, `board` being the position before the move.
+pub fn move_to_san(board: &Board, mv: Move) -> String {
+    let mut san = String::new();
+
+    if mv.is_castling() {
+        san.push_str(if mv.is_king_side_castling() {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        let src_square = mv.src_square();
+        let dst_square = mv.dst_square();
+        let is_capture = mv.is_capture() || mv.is_en_passant();
+
+        if mv.piece() == Piece::Pawn {
+            if is_capture {
+                san.push(src_square.name().chars().next().unwrap());
+            }
+        } else {
+            san.push(piece_letter(mv.piece()));
+
+            // Other pieces of the same type which can go to the same square.
+            let rivals: Vec<Square> = generate_moves(board)
+                .moves()
+                .iter()
+                .filter(|other| {
+                    other.piece() == mv.piece()
+                        && other.dst_square() == dst_square
+                        && other.src_square() != src_square
+                })
+                .map(|other| other.src_square())
+                .collect();
+
+            if !rivals.is_empty() {
+                let name = src_square.name();
+                if rivals.iter().all(|rival| rival.file() != src_square.file()) {
+                    san.push_str(&name[..1]);
+                } else if rivals.iter().all(|rival| rival.rank() != src_square.rank()) {
+                    san.push_str(&name[1..]);
+                } else {
+                    san.push_str(name);
+                }
+            }
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(dst_square.name());
+
+        if let Some(promotion_piece) = mv.promotion_piece() {
+            san.push('=');
+            san.push(piece_letter(promotion_piece));
+        }
+    }
+
+    let mut next_board = *board;
+    next_board.play_move(mv);
+    if is_in_check(&next_board, next_board.side_to_move) {
+        san.push(if generate_moves(&next_board).is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+
+    san
+}
+
+/// What a SAN string tells about the move it describes.
+#[derive(Debug, Default)]
+struct SanPattern {
+    /// Kingside (true) or queenside (false) castling
+    castling: Option<bool>,
+    piece: Option<Piece>,
+    src_file: Option<u8>,
+    src_rank: Option<u8>,
+    capture: bool,
+    dst_square: Option<Square>,
+    promotion_piece: Option<Piece>,
+}
+
+impl SanPattern {
+    fn parse(san: &str, mode: SanMode) -> Option<Self> {
+        let mut pattern = Self::default();
+
+        // Check, mate and annotation markers are checked against the canonical SAN
+        // in strict mode, and ignored otherwise.
+        let body = san.trim_end_matches(['+', '#', '!', '?']);
+
+        let castles = match mode {
+            SanMode::Strict => [("O-O", true), ("O-O-O", false)].as_slice(),
+            SanMode::Lenient => [
+                ("O-O", true),
+                ("O-O-O", false),
+                ("0-0", true),
+                ("0-0-0", false),
+            ]
+            .as_slice(),
+        };
+        if let Some((_, kingside)) = castles.iter().find(|(castle, _)| *castle == body) {
+            pattern.castling = Some(*kingside);
+            return Some(pattern);
+        }
+
+        let mut chars: Vec<char> = body.chars().collect();
+
+        if let Some(piece) = chars.first().copied().and_then(piece_from_letter) {
+            pattern.piece = Some(piece);
+            chars.remove(0);
+        } else {
+            pattern.piece = Some(Piece::Pawn);
+        }
+
+        // Promotion, "=Q", or also "Q" and "q" right after the rank in lenient mode.
+        let promotion_letter = match chars.as_slice() {
+            [.., '=', letter] => Some(*letter),
+            [.., rank, letter] if mode == SanMode::Lenient && rank.is_ascii_digit() => {
+                Some(*letter)
+            }
+            _ => None,
+        };
+        if let Some(mut letter) = promotion_letter {
+            if mode == SanMode::Lenient {
+                letter = letter.to_ascii_uppercase();
+            }
+            let promotion_piece =
+                piece_from_letter(letter).filter(|piece| *piece != Piece::King)?;
+            pattern.promotion_piece = Some(promotion_piece);
+            chars.truncate(chars.len() - 1);
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        // Destination square
+        if chars.len() < 2 {
+            return None;
+        }
+        let dst: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        pattern.dst_square = Some(Square::from_name(&dst)?);
+
+        if chars.last() == Some(&'x') {
+            pattern.capture = true;
+            chars.pop();
+        }
+
+        // Disambiguation, file and/or rank of the source square.
+        for c in chars {
+            match c {
+                'a'..='h' if pattern.src_file.is_none() && pattern.src_rank.is_none() => {
+                    pattern.src_file = Some(c as u8 - b'a');
+                }
+                '1'..='8' if pattern.src_rank.is_none() => {
+                    pattern.src_rank = Some(b'8' - c as u8);
+                }
+                _ => return None,
+            }
+        }
+
+        Some(pattern)
+    }
+
+    fn matches(&self, mv: Move, mode: SanMode) -> bool {
+        if let Some(kingside) = self.castling {
+            return mv.is_castling() && mv.is_king_side_castling() == kingside;
+        }
+
+        // Castling is only ever written O-O or O-O-O, not as a king move.
+        if mv.is_castling() {
+            return false;
+        }
+
+        let src_square = mv.src_square();
+
+        Some(mv.piece()) == self.piece
+            && Some(mv.dst_square()) == self.dst_square
+            && mv.promotion_piece() == self.promotion_piece
+            && self.src_file.is_none_or(|file| file == src_square.file())
+            && self.src_rank.is_none_or(|rank| rank == src_square.rank())
+            && (mode == SanMode::Lenient || (mv.is_capture() || mv.is_en_passant()) == self.capture)
+    }
+}
+
+/// Finds the legal move described by `san` in the given position.
+pub fn parse_san(board: &Board, san: &str, mode: SanMode) -> Result<Move, SanError> {
+    let pattern = SanPattern::parse(san, mode).ok_or_else(|| SanError::Invalid(san.to_string()))?;
+
+    let moves = generate_moves(board);
+    let mut candidates = moves
+        .moves()
+        .iter()
+        .filter(|mv| pattern.matches(**mv, mode));
+
+    let mv = *candidates
+        .next()
+        .ok_or_else(|| SanError::IllegalMove(san.to_string()))?;
+    if candidates.next().is_some() {
+        return Err(SanError::AmbiguousMove(san.to_string()));
+    }
+
+    if mode == SanMode::Strict {
+        let expected = move_to_san(board, mv);
+        if expected != san {
+            return Err(SanError::NotCanonical {
+                san: san.to_string(),
+                expected,
+            });
+        }
+    }
+
+    Ok(mv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san_moves(fen: &str) -> Vec<String> {
+        let board = Board::from_fen(fen).unwrap();
+        let mut moves: Vec<String> = generate_moves(&board)
+            .moves()
+            .iter()
+            .map(|mv| move_to_san(&board, *mv))
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn moves_are_disambiguated() {
+        // Knights b1 and f3 can both go to d2, rooks a1 and a5 to a3,
+        // and queens e4, h4 and h1 to e1.
+        let moves = san_moves("2k5/8/8/R7/4Q2Q/1K3N2/8/RN5Q w - - 0 1");
+
+        for expected in ["Nbd2", "Nfd2", "R1a3", "R5a3", "Qee1", "Q1e1", "Qh4e1"] {
+            assert!(
+                moves.contains(&expected.to_string()),
+                "{} not in {:?}",
+                expected,
+                moves
+            );
+        }
+    }
+
+    #[test]
+    fn special_moves_are_formatted() {
+        let moves = san_moves("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1");
+
+        for expected in ["O-O", "O-O-O", "exd6", "b8=Q+", "bxa8=N", "Rxa8+"] {
+            assert!(
+                moves.contains(&expected.to_string()),
+                "{} not in {:?}",
+                expected,
+                moves
+            );
+        }
+    }
+
+    #[test]
+    fn mate_is_marked() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mv = parse_san(&board, "Ra8#", SanMode::Strict).unwrap();
+
+        assert_eq!(mv.to_uci(), "a1a8");
+    }
+
+    #[test]
+    fn every_legal_move_round_trips_in_strict_mode() {
+        let fens = [
+            crate::fens::STARTING_BOARD_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen(fen).unwrap();
+            for mv in generate_moves(&board).moves() {
+                let san = move_to_san(&board, *mv);
+                assert_eq!(parse_san(&board, &san, SanMode::Strict), Ok(*mv), "{}", san);
+            }
+        }
+    }
+
+    #[test]
+    fn lenient_mode_accepts_common_variations() {
+        let board = Board::from_fen("r3k3/1P6/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+
+        for (san, uci) in [
+            ("0-0", "e1g1"),
+            ("O-O+", "e1g1"),
+            ("b8Q", "b7b8q"),
+            ("b8q", "b7b8q"),
+            ("b8=q", "b7b8q"),
+            ("bxa8=R", "b7a8r"),
+            ("ba8N", "b7a8n"),
+            ("Rh1h7!?", "h1h7"),
+        ] {
+            assert_eq!(
+                parse_san(&board, san, SanMode::Lenient).map(Move::to_uci),
+                Ok(uci.to_string()),
+                "{}",
+                san
+            );
+        }
+
+        assert!(matches!(
+            parse_san(&board, "0-0", SanMode::Strict),
+            Err(SanError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse_san(&board, "b8Q", SanMode::Strict),
+            Err(SanError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse_san(&board, "b8=Q", SanMode::Strict),
+            Err(SanError::NotCanonical { .. })
+        ));
+    }
+
+    #[test]
+    fn illegal_and_ambiguous_moves_are_rejected() {
+        let board = Board::from_fen("4k3/R7/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+
+        assert_eq!(
+            parse_san(&board, "Ra4", SanMode::Lenient),
+            Err(SanError::AmbiguousMove("Ra4".to_string()))
+        );
+        assert_eq!(
+            parse_san(&board, "Nd3", SanMode::Lenient),
+            Err(SanError::IllegalMove("Nd3".to_string()))
+        );
+        assert_eq!(
+            parse_san(&board, "Xe4", SanMode::Lenient),
+            Err(SanError::Invalid("Xe4".to_string()))
+        );
+    }
+}
@@ -0,0 +1,573 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::board::*;
+use crate::eval::{evaluate, MG_PIECE_VALUES};
+use crate::move_generator::*;
+use crate::tt::{Bound, TranspositionTable};
+
+/// Maximum search depth, in plies.
+pub const MAX_PLY: usize = 128;
+
+/// Bounds of the alpha-beta window.
+pub const INFINITY: i32 = 32000;
+
+/// Score of a checkmate delivered at the root. Mates found deeper in the tree are
+/// scored MATE_SCORE - ply, so that shorter mates are preferred.
+pub const MATE_SCORE: i32 = 31000;
+
+/// Any score beyond this value (in absolute) is a mate score.
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - MAX_PLY as i32;
+
+/// The stop conditions are checked every this many nodes (must be a power of two).
+const STOP_CHECK_INTERVAL: u64 = 1024;
+
+/// Conditions under which the search stops. The search also stops when
+/// its stop flag is raised.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+/// Result of a completed iterative deepening iteration.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub depth: u32,
+    pub score: i32,
+    pub best_move: Move,
+    pub pv: Vec<Move>,
+    pub nodes: u64,
+    pub time: Duration,
+
+    /// Transposition table occupancy, in permill.
+    pub hashfull: usize,
+}
+
+pub struct Search<'s> {
+    limits: SearchLimits,
+    stop: &'s AtomicBool,
+    stopped: bool,
+
+    tt: &'s mut TranspositionTable,
+
+    start_time: Instant,
+    nodes: u64,
+
+    /// Triangular principal variation table: pv_table[ply] holds the best line
+    /// found from `ply`, with pv_length[ply] its end.
+    pv_table: [[Move; MAX_PLY]; MAX_PLY],
+    pv_length: [usize; MAX_PLY],
+
+    /// Principal variation of the previous iteration, used for move ordering.
+    previous_pv: Vec<Move>,
+
+    /// Zobrist keys of the positions leading to the current node, the game
+    /// history included, for repetition detection.
+    hashes: Vec<u64>,
+}
+
+impl<'s> Search<'s> {
+    pub fn new(limits: SearchLimits, stop: &'s AtomicBool, tt: &'s mut TranspositionTable) -> Self {
+        Self {
+            limits,
+            stop,
+            stopped: false,
+            tt,
+            start_time: Instant::now(),
+            nodes: 0,
+            pv_table: [[Move::NULL; MAX_PLY]; MAX_PLY],
+            pv_length: [0; MAX_PLY],
+            previous_pv: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    /// Iterative deepening: searches the position at increasing depths until one of
+    /// the limits is reached. `on_iteration` is called after each completed iteration.
+    /// Returns the result of the last completed iteration, if any.
+    pub fn run<F>(&mut self, position: &Position, mut on_iteration: F) -> Option<SearchResult>
+    where
+        F: FnMut(&SearchResult),
+    {
+        let board = position.board();
+        self.hashes = position.previous_hashes().collect();
+        self.hashes.reverse();
+
+        self.start_time = Instant::now();
+        self.nodes = 0;
+        self.stopped = false;
+        self.previous_pv.clear();
+        self.tt.new_search();
+
+        let max_depth = self
+            .limits
+            .depth
+            .unwrap_or(MAX_PLY as u32 - 1)
+            .min(MAX_PLY as u32 - 1);
+
+        let mut result: Option<SearchResult> = None;
+
+        for depth in 1..=max_depth {
+            let score = self.negamax(board, depth, 0, -INFINITY, INFINITY);
+
+            // An interrupted iteration cannot be trusted.
+            if self.stopped {
+                break;
+            }
+
+            // No legal move, nothing to search.
+            if self.pv_length[0] == 0 {
+                break;
+            }
+
+            let mut pv = self.pv_table[0][..self.pv_length[0]].to_vec();
+            self.extend_pv(board, &mut pv, depth as usize);
+
+            let iteration = SearchResult {
+                depth,
+                score,
+                best_move: self.pv_table[0][0],
+                pv,
+                nodes: self.nodes,
+                time: self.start_time.elapsed(),
+                hashfull: self.tt.hashfull(),
+            };
+            self.previous_pv = iteration.pv.clone();
+            on_iteration(&iteration);
+            result = Some(iteration);
+
+            // A forced mate has been found, searching deeper will not find anything better.
+            if score.abs() >= MATE_THRESHOLD && MATE_SCORE - score.abs() <= depth as i32 {
+                break;
+            }
+
+            // The next iteration will most likely take longer than all the previous
+            // ones together, do not start it if it is not going to complete.
+            if let Some(time) = self.limits.time {
+                if self.start_time.elapsed() * 2 > time {
+                    break;
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Transposition table cutoffs return without a principal variation, which cuts
+    /// the line short. Completes it up to `length` moves with the best moves stored
+    /// in the table, as long as they are legal and do not repeat a position.
+    fn extend_pv(&self, root: &Board, pv: &mut Vec<Move>, length: usize) {
+        let mut board = *root;
+        let mut hashes = vec![board.hash];
+        for mv in pv.iter() {
+            board.play_move(*mv);
+            hashes.push(board.hash);
+        }
+
+        while pv.len() < length {
+            let Some(mv) = self
+                .tt
+                .probe(board.hash)
+                .and_then(|entry| entry.best_move())
+            else {
+                break;
+            };
+            if !generate_moves(&board).moves().contains(&mv) {
+                break;
+            }
+
+            board.play_move(mv);
+            if hashes.contains(&board.hash) {
+                break;
+            }
+            hashes.push(board.hash);
+            pv.push(mv);
+        }
+    }
+
+    /// Fail-soft negamax alpha-beta search. The score is given from the side to move
+    /// point of view.
+    fn negamax(&mut self, board: &Board, depth: u32, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        if ply > 0 && self.is_draw(board) {
+            self.pv_length[ply] = ply;
+            return 0;
+        }
+
+        if depth == 0 {
+            return self.quiescence(board, ply, alpha, beta);
+        }
+
+        self.pv_length[ply] = ply;
+        self.nodes += 1;
+
+        if self.should_stop() {
+            return 0;
+        }
+
+        let tt_entry = self.tt.probe(board.hash);
+        let tt_move = tt_entry.and_then(|entry| entry.best_move());
+
+        // The root is always searched, so that we get a complete principal variation.
+        if let Some(entry) = tt_entry.filter(|entry| ply > 0 && entry.depth() >= depth) {
+            let score = entry.score(ply);
+
+            let usable = match entry.bound() {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+
+            if usable {
+                return score;
+            }
+        }
+
+        let mut moves = generate_moves(board);
+
+        if moves.is_empty() {
+            // Checkmate or stalemate
+            return if is_in_check(board, board.side_to_move) {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return evaluate(board);
+        }
+
+        self.order_moves(board, &mut moves, ply, tt_move);
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        // Copy-make benchmarks as fast as make/unmake (`chess_engine bench unmake`),
+        // and keeps the recursion simpler.
+        let mut my_board = *board;
+        self.hashes.push(board.hash);
+
+        for mv in moves.moves() {
+            let old_board = my_board.play_move(*mv);
+            let score = -self.negamax(&my_board, depth - 1, ply + 1, -beta, -alpha);
+            my_board = old_board;
+
+            if self.stopped {
+                self.hashes.pop();
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+
+                if score > alpha {
+                    alpha = score;
+                    best_move = Some(*mv);
+
+                    // This move becomes the head of the principal variation
+                    // at this ply, followed by the one of the child node.
+                    self.pv_table[ply][ply] = *mv;
+                    for next_ply in ply + 1..self.pv_length[ply + 1] {
+                        self.pv_table[ply][next_ply] = self.pv_table[ply + 1][next_ply];
+                    }
+                    self.pv_length[ply] = self.pv_length[ply + 1].max(ply + 1);
+
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.hashes.pop();
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt
+            .store(board.hash, depth, best_score, bound, best_move, ply);
+
+        best_score
+    }
+
+    /// Searches the captures (and queen promotions) until the position is quiet,
+    /// so that the static evaluation is not taken in the middle of an exchange.
+    /// When in check, all the evasions are searched instead.
+    fn quiescence(&mut self, board: &Board, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv_length[ply] = ply;
+        self.nodes += 1;
+
+        if self.should_stop() {
+            return 0;
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return evaluate(board);
+        }
+
+        let in_check = is_in_check(board, board.side_to_move);
+
+        let (mut moves, mut best_score) = if in_check {
+            let moves = generate_moves(board);
+            if moves.is_empty() {
+                return -MATE_SCORE + ply as i32;
+            }
+
+            (moves, -INFINITY)
+        } else {
+            // Stand pat: the side to move is not forced to capture, so the static
+            // evaluation is a lower bound of the position score.
+            let stand_pat = evaluate(board);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+
+            (generate_captures(board), stand_pat)
+        };
+
+        order_captures(board, &mut moves);
+
+        let mut my_board = *board;
+
+        for mv in moves.moves() {
+            let old_board = my_board.play_move(*mv);
+            let score = -self.quiescence(&my_board, ply + 1, -beta, -alpha);
+            my_board = old_board;
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+
+                if score > alpha {
+                    alpha = score;
+
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        best_score
+    }
+
+    /// Tries the move of the previous iteration principal variation first at each ply:
+    /// it is likely to still be the best one and gives a good bound to prune the others.
+    /// The transposition table move, when there is one, is even more likely to be the
+    /// best as it comes from this very position. Captures come next, then the quiet moves.
+    fn order_moves(&self, board: &Board, moves: &mut Moves, ply: usize, tt_move: Option<Move>) {
+        order_captures(board, moves);
+
+        let moves = moves.moves_mut();
+        for first_move in [self.previous_pv.get(ply).copied(), tt_move]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(index) = moves.iter().position(|mv| *mv == first_move) {
+                moves[..=index].rotate_right(1);
+            }
+        }
+    }
+
+    /// Draws by rule. Unlike in a game, a single repetition is scored as a draw:
+    /// if repeating was the best option once, it will be again.
+    /// Checkmate takes precedence over the fifty-move rule.
+    fn is_draw(&self, board: &Board) -> bool {
+        let repetition = self
+            .hashes
+            .iter()
+            .rev()
+            .take(board.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|hash| *hash == board.hash);
+
+        let fifty_moves = board.halfmove_clock >= FIFTY_MOVE_RULE_PLIES
+            && (!is_in_check(board, board.side_to_move) || !generate_moves(board).is_empty());
+
+        repetition || fifty_moves || board.has_insufficient_material()
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+
+        if self.nodes & (STOP_CHECK_INTERVAL - 1) == 0 {
+            let out_of_time = self
+                .limits
+                .time
+                .is_some_and(|time| self.start_time.elapsed() >= time);
+            let out_of_nodes = self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes);
+
+            self.stopped = out_of_time || out_of_nodes || self.stop.load(Ordering::Relaxed);
+        }
+
+        self.stopped
+    }
+}
+
+/// Sorts the moves by MVV-LVA (Most Valuable Victim - Least Valuable Attacker):
+/// the biggest material gains are tried first, and among captures of the same piece,
+/// the ones made with the cheapest attacker.
+fn order_captures(board: &Board, moves: &mut Moves) {
+    moves
+        .moves_mut()
+        .sort_by_cached_key(|mv| std::cmp::Reverse(mvv_lva(board, *mv)));
+}
+
+fn mvv_lva(board: &Board, mv: Move) -> i32 {
+    let victim = if mv.is_en_passant() {
+        Some(Piece::Pawn)
+    } else if mv.is_capture() {
+        board.piece_at(mv.dst_square()).map(SidedPiece::piece)
+    } else {
+        None
+    };
+
+    let mut gain = victim.map_or(0, |victim| MG_PIECE_VALUES[victim as usize]);
+    if let Some(promotion_piece) = mv.promotion_piece() {
+        gain += MG_PIECE_VALUES[promotion_piece as usize];
+    }
+
+    // Piece values are far enough apart for the attacker to only break ties.
+    gain - mv.piece() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fens;
+
+    fn search(position: &Position, depth: u32) -> Option<SearchResult> {
+        let stop = AtomicBool::new(false);
+        let mut tt = TranspositionTable::new(1);
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+
+        Search::new(limits, &stop, &mut tt).run(position, |_| {})
+    }
+
+    fn search_fen(fen: &str, depth: u32) -> Option<SearchResult> {
+        search(&Position::new(Board::from_fen(fen).unwrap()), depth)
+    }
+
+    /// Score of the root node alone, for positions without any legal move.
+    fn root_score(fen: &str) -> i32 {
+        let board = Board::from_fen(fen).unwrap();
+        let stop = AtomicBool::new(false);
+        let mut tt = TranspositionTable::new(1);
+
+        Search::new(SearchLimits::default(), &stop, &mut tt)
+            .negamax(&board, 1, 0, -INFINITY, INFINITY)
+    }
+
+    #[test]
+    fn mate_in_one_is_found() {
+        let result = search_fen(fens::BACKRANK_MATE_FEN, 4).unwrap();
+
+        assert_eq!(result.best_move.to_uci(), "b1b8");
+        assert_eq!(result.score, MATE_SCORE - 1);
+    }
+
+    #[test]
+    fn mate_in_two_is_found() {
+        // 1. Nf6+ gxf6 2. Bxf7#
+        let fen = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1";
+        let result = search_fen(fen, 5).unwrap();
+
+        assert_eq!(result.best_move.to_uci(), "d5f6");
+        assert_eq!(result.score, MATE_SCORE - 3);
+    }
+
+    #[test]
+    fn positions_without_moves_are_not_searched() {
+        let mated = "1R4k1/5ppp/8/8/8/8/8/6K1 b - - 1 1";
+        assert!(search_fen(mated, 3).is_none());
+        assert_eq!(root_score(mated), -MATE_SCORE);
+
+        let stalemate = "7k/5Q2/8/8/8/8/8/6K1 b - - 0 1";
+        assert!(search_fen(stalemate, 3).is_none());
+        assert_eq!(root_score(stalemate), 0);
+    }
+
+    #[test]
+    fn checkmate_takes_precedence_over_the_fifty_move_rule() {
+        // Rb8# is the hundredth half move without capture or pawn move.
+        let result = search_fen("6k1/5ppp/8/8/8/8/8/1R4K1 w - - 99 80", 4).unwrap();
+        assert_eq!(result.best_move.to_uci(), "b1b8");
+        assert_eq!(result.score, MATE_SCORE - 1);
+
+        // Any other move draws, even a check.
+        let result = search_fen("6k1/6pp/8/8/8/8/8/1R4K1 w - - 99 80", 4).unwrap();
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn repetitions_are_scored_as_draws() {
+        // White is a queen down, but can only move the king back and forth.
+        let mut position = Position::new(Board::from_fen("k7/8/8/8/8/8/q7/7K w - - 0 1").unwrap());
+        for uci in ["h1g1", "a8b8", "g1h1", "b8a8"] {
+            let mv = position.board().parse_uci_move(uci).unwrap();
+            position.make_move(mv);
+        }
+
+        let result = search(&position, 4).unwrap();
+        assert_eq!(result.best_move.to_uci(), "h1g1");
+        assert_eq!(result.score, 0);
+
+        // Without the history, the queen wins.
+        let result = search_fen(&position.board().to_fen(), 4).unwrap();
+        assert!(result.score < -500);
+    }
+
+    #[test]
+    fn principal_variations_are_not_cut_by_tt_hits() {
+        let fens = [
+            fens::STARTING_BOARD_FEN,
+            "4k2r/8/8/8/8/8/8/4K3 w k - 0 1",
+            "r3k2r/8/8/8/8/8/8/4K3 w kq - 0 1",
+            "4k3/8/8/8/8/8/8/4K2R b K - 0 1",
+        ];
+
+        for fen in fens {
+            let board = Board::from_fen(fen).unwrap();
+            let stop = AtomicBool::new(false);
+            let mut tt = TranspositionTable::new(1);
+            let limits = SearchLimits {
+                depth: Some(6),
+                ..Default::default()
+            };
+
+            let mut iterations = Vec::new();
+            Search::new(limits, &stop, &mut tt).run(&Position::new(board), |iteration| {
+                iterations.push((iteration.depth, iteration.score, iteration.pv.clone()))
+            });
+
+            for (depth, score, pv) in iterations {
+                // Draws and mates may legitimately end the line early.
+                if score != 0 && score.abs() < MATE_THRESHOLD {
+                    assert!(pv.len() >= depth as usize, "{} depth {}", fen, depth);
+                }
+
+                let mut board = board;
+                for mv in pv {
+                    assert!(generate_moves(&board).moves().contains(&mv), "{}", fen);
+                    board.play_move(mv);
+                }
+            }
+        }
+    }
+}
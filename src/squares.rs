@@ -1,72 +1,134 @@
 #![allow(non_upper_case_globals, dead_code)]
 
+use std::fmt;
+
 use phf::phf_map;
 
-pub const a8: i32 = 0;
-pub const b8: i32 = 1;
-pub const c8: i32 = 2;
-pub const d8: i32 = 3;
-pub const e8: i32 = 4;
-pub const f8: i32 = 5;
-pub const g8: i32 = 6;
-pub const h8: i32 = 7;
-pub const a7: i32 = 8;
-pub const b7: i32 = 9;
-pub const c7: i32 = 10;
-pub const d7: i32 = 11;
-pub const e7: i32 = 12;
-pub const f7: i32 = 13;
-pub const g7: i32 = 14;
-pub const h7: i32 = 15;
-pub const a6: i32 = 16;
-pub const b6: i32 = 17;
-pub const c6: i32 = 18;
-pub const d6: i32 = 19;
-pub const e6: i32 = 20;
-pub const f6: i32 = 21;
-pub const g6: i32 = 22;
-pub const h6: i32 = 23;
-pub const a5: i32 = 24;
-pub const b5: i32 = 25;
-pub const c5: i32 = 26;
-pub const d5: i32 = 27;
-pub const e5: i32 = 28;
-pub const f5: i32 = 29;
-pub const g5: i32 = 30;
-pub const h5: i32 = 31;
-pub const a4: i32 = 32;
-pub const b4: i32 = 33;
-pub const c4: i32 = 34;
-pub const d4: i32 = 35;
-pub const e4: i32 = 36;
-pub const f4: i32 = 37;
-pub const g4: i32 = 38;
-pub const h4: i32 = 39;
-pub const a3: i32 = 40;
-pub const b3: i32 = 41;
-pub const c3: i32 = 42;
-pub const d3: i32 = 43;
-pub const e3: i32 = 44;
-pub const f3: i32 = 45;
-pub const g3: i32 = 46;
-pub const h3: i32 = 47;
-pub const a2: i32 = 48;
-pub const b2: i32 = 49;
-pub const c2: i32 = 50;
-pub const d2: i32 = 51;
-pub const e2: i32 = 52;
-pub const f2: i32 = 53;
-pub const g2: i32 = 54;
-pub const h2: i32 = 55;
-pub const a1: i32 = 56;
-pub const b1: i32 = 57;
-pub const c1: i32 = 58;
-pub const d1: i32 = 59;
-pub const e1: i32 = 60;
-pub const f1: i32 = 61;
-pub const g1: i32 = 62;
-pub const h1: i32 = 63;
-pub const NO_SQUARE: i32 = -1;
+/// A square of the board, indexed from a8 (0) to h1 (63), rank by rank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Square(u8);
+
+impl Square {
+    pub const fn new(index: u8) -> Self {
+        debug_assert!(index < 64);
+        Self(index)
+    }
+
+    /// `rank` follows the squares layout: 0 is the 8th rank, 7 the 1st one.
+    pub const fn from_rank_file(rank: u8, file: u8) -> Self {
+        Self::new(rank * 8 + file)
+    }
+
+    /// Parses a square name, such as "e4".
+    pub fn from_name(name: &str) -> Option<Self> {
+        CELL_TO_SQUARE.get(name).map(|index| Self(*index))
+    }
+
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// 0 is the 8th rank, 7 the 1st one.
+    pub const fn rank(self) -> u8 {
+        self.0 / 8
+    }
+
+    /// 0 is the a-file, 7 the h-file.
+    pub const fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    /// The square `offset` indices away. The caller is responsible for staying
+    /// on the board, no wrapping check is done.
+    pub const fn offset(self, offset: i8) -> Self {
+        Self::new(self.0.wrapping_add_signed(offset))
+    }
+
+    /// Mirror square along the horizontal axis (e.g. a1 <-> a8).
+    pub const fn flip(self) -> Self {
+        Self(self.0 ^ 56)
+    }
+
+    pub const fn name(self) -> &'static str {
+        CELL_NAMES[self.0 as usize]
+    }
+
+    /// All the squares, from a8 to h1.
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(Self)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub const a8: Square = Square(0);
+pub const b8: Square = Square(1);
+pub const c8: Square = Square(2);
+pub const d8: Square = Square(3);
+pub const e8: Square = Square(4);
+pub const f8: Square = Square(5);
+pub const g8: Square = Square(6);
+pub const h8: Square = Square(7);
+pub const a7: Square = Square(8);
+pub const b7: Square = Square(9);
+pub const c7: Square = Square(10);
+pub const d7: Square = Square(11);
+pub const e7: Square = Square(12);
+pub const f7: Square = Square(13);
+pub const g7: Square = Square(14);
+pub const h7: Square = Square(15);
+pub const a6: Square = Square(16);
+pub const b6: Square = Square(17);
+pub const c6: Square = Square(18);
+pub const d6: Square = Square(19);
+pub const e6: Square = Square(20);
+pub const f6: Square = Square(21);
+pub const g6: Square = Square(22);
+pub const h6: Square = Square(23);
+pub const a5: Square = Square(24);
+pub const b5: Square = Square(25);
+pub const c5: Square = Square(26);
+pub const d5: Square = Square(27);
+pub const e5: Square = Square(28);
+pub const f5: Square = Square(29);
+pub const g5: Square = Square(30);
+pub const h5: Square = Square(31);
+pub const a4: Square = Square(32);
+pub const b4: Square = Square(33);
+pub const c4: Square = Square(34);
+pub const d4: Square = Square(35);
+pub const e4: Square = Square(36);
+pub const f4: Square = Square(37);
+pub const g4: Square = Square(38);
+pub const h4: Square = Square(39);
+pub const a3: Square = Square(40);
+pub const b3: Square = Square(41);
+pub const c3: Square = Square(42);
+pub const d3: Square = Square(43);
+pub const e3: Square = Square(44);
+pub const f3: Square = Square(45);
+pub const g3: Square = Square(46);
+pub const h3: Square = Square(47);
+pub const a2: Square = Square(48);
+pub const b2: Square = Square(49);
+pub const c2: Square = Square(50);
+pub const d2: Square = Square(51);
+pub const e2: Square = Square(52);
+pub const f2: Square = Square(53);
+pub const g2: Square = Square(54);
+pub const h2: Square = Square(55);
+pub const a1: Square = Square(56);
+pub const b1: Square = Square(57);
+pub const c1: Square = Square(58);
+pub const d1: Square = Square(59);
+pub const e1: Square = Square(60);
+pub const f1: Square = Square(61);
+pub const g1: Square = Square(62);
+pub const h1: Square = Square(63);
 
 pub const CELL_NAMES: [&str; 64] = [
     "a8", "b8", "c8", "d8", "e8", "f8", "g8", "h8", "a7", "b7", "c7", "d7", "e7", "f7", "g7", "h7",
@@ -75,7 +137,7 @@ pub const CELL_NAMES: [&str; 64] = [
     "a2", "b2", "c2", "d2", "e2", "f2", "g2", "h2", "a1", "b1", "c1", "d1", "e1", "f1", "g1", "h1",
 ];
 
-pub const CELL_TO_SQUARE: phf::Map<&'static str, i32> = phf_map! {
+pub const CELL_TO_SQUARE: phf::Map<&'static str, u8> = phf_map! {
     "a8" => 0,
     "b8" => 1,
     "c8" => 2,
@@ -141,3 +203,50 @@ pub const CELL_TO_SQUARE: phf::Map<&'static str, i32> = phf_map! {
     "g1" => 62,
     "h1" => 63,
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn squares_are_named() {
+        for square in Square::all() {
+            assert_eq!(Square::from_name(square.name()), Some(square));
+            assert_eq!(square.to_string(), square.name());
+        }
+
+        assert_eq!(Square::from_name("a8"), Some(a8));
+        assert_eq!(Square::from_name("h1"), Some(h1));
+        assert_eq!(e4.name(), "e4");
+        for invalid in ["", "e", "e9", "i1", "E4", "e44"] {
+            assert_eq!(Square::from_name(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn ranks_and_files_follow_the_layout() {
+        assert_eq!((a8.rank(), a8.file()), (0, 0));
+        assert_eq!((h1.rank(), h1.file()), (7, 7));
+        assert_eq!((e4.rank(), e4.file()), (4, 4));
+
+        for square in Square::all() {
+            assert_eq!(Square::from_rank_file(square.rank(), square.file()), square);
+            assert_eq!(Square::new(square.index() as u8), square);
+        }
+        assert_eq!(Square::all().count(), 64);
+    }
+
+    #[test]
+    fn squares_are_offset_and_flipped() {
+        assert_eq!(e2.offset(-16), e4);
+        assert_eq!(e7.offset(16), e5);
+        assert_eq!(a1.flip(), a8);
+        assert_eq!(e4.flip(), e5);
+
+        for square in Square::all() {
+            assert_eq!(square.flip().flip(), square);
+            assert_eq!(square.flip().file(), square.file());
+            assert_eq!(square.flip().rank(), 7 - square.rank());
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::move_generator::Move;
+use crate::search::MATE_THRESHOLD;
+
+/// Default size of the transposition table, in megabytes.
+pub const DEFAULT_HASH_SIZE_MB: usize = 16;
+pub const MIN_HASH_SIZE_MB: usize = 1;
+pub const MAX_HASH_SIZE_MB: usize = 32768;
+
+/// Number of buckets looked at to estimate how full the table is.
+const HASHFULL_SAMPLE_SIZE: usize = 1000;
+
+/// How the stored score relates to the actual score of the position.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Bound {
+    /// The score is exact (PV node).
+    #[default]
+    Exact,
+
+    /// The search failed high: the actual score is at least the stored one.
+    Lower,
+
+    /// The search failed low: the actual score is at most the stored one.
+    Upper,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct TtEntry {
+    /// Full Zobrist key of the position, to tell index collisions apart.
+    key: u64,
+
+    /// Raw encoding of the best move found, 0 if none.
+    best_move: u32,
+
+    /// Score, stored relative to the node in case of a mate (see `store`).
+    score: i16,
+
+    depth: u8,
+    bound: Bound,
+
+    /// Search generation this entry has been written during.
+    generation: u8,
+}
+
+impl TtEntry {
+    pub fn best_move(&self) -> Option<Move> {
+        (self.best_move != 0).then(|| Move::from_bits(self.best_move))
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth as u32
+    }
+
+    pub fn bound(&self) -> Bound {
+        self.bound
+    }
+
+    /// Score of the entry, as seen from the root of a search reaching it at `ply`.
+    pub fn score(&self, ply: usize) -> i32 {
+        let score = self.score as i32;
+
+        if score >= MATE_THRESHOLD {
+            score - ply as i32
+        } else if score <= -MATE_THRESHOLD {
+            score + ply as i32
+        } else {
+            score
+        }
+    }
+}
+
+/// Each index holds two entries: the first one is only replaced by deeper (or newer)
+/// searches, the second one is always replaced.
+#[derive(Debug, Default, Clone, Copy)]
+struct Bucket {
+    depth_preferred: TtEntry,
+    always_replace: TtEntry,
+}
+
+/// Cache of search results, indexed by position hash.
+pub struct TranspositionTable {
+    /// Always holds a power of two number of buckets, so that the index is a simple mask.
+    buckets: Vec<Bucket>,
+    generation: u8,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        Self {
+            buckets: vec![Bucket::default(); Self::bucket_count(size_mb)],
+            generation: 0,
+        }
+    }
+
+    /// Largest power of two number of buckets fitting in the given size.
+    fn bucket_count(size_mb: usize) -> usize {
+        let size_mb = size_mb.clamp(MIN_HASH_SIZE_MB, MAX_HASH_SIZE_MB);
+        let count = size_mb * 1024 * 1024 / std::mem::size_of::<Bucket>();
+
+        1 << count.ilog2()
+    }
+
+    /// Actual size of the table, the requested one rounded down to a power of two buckets.
+    pub fn size_mb(&self) -> usize {
+        self.buckets.len() * std::mem::size_of::<Bucket>() / (1024 * 1024)
+    }
+
+    /// Reallocates the table, all the entries are lost.
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = Self::new(size_mb);
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.fill(Bucket::default());
+        self.generation = 0;
+    }
+
+    /// Must be called before each search, so that entries of the previous ones
+    /// get replaced first.
+    pub(crate) fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn index(&self, key: u64) -> usize {
+        key as usize & (self.buckets.len() - 1)
+    }
+
+    pub(crate) fn probe(&self, key: u64) -> Option<TtEntry> {
+        let bucket = &self.buckets[self.index(key)];
+
+        [bucket.depth_preferred, bucket.always_replace]
+            .into_iter()
+            .find(|entry| entry.key == key && entry.depth > 0)
+    }
+
+    /// Stores a search result. `ply` is the distance from the root, and is needed
+    /// to store mate scores relative to this node rather than to the root.
+    pub(crate) fn store(
+        &mut self,
+        key: u64,
+        depth: u32,
+        score: i32,
+        bound: Bound,
+        best_move: Option<Move>,
+        ply: usize,
+    ) {
+        let score = if score >= MATE_THRESHOLD {
+            score + ply as i32
+        } else if score <= -MATE_THRESHOLD {
+            score - ply as i32
+        } else {
+            score
+        };
+
+        let generation = self.generation;
+        let index = self.index(key);
+        let bucket = &mut self.buckets[index];
+
+        // Keep the best move we knew of when the new search did not find any.
+        let best_move = best_move.map_or_else(
+            || {
+                [bucket.depth_preferred, bucket.always_replace]
+                    .into_iter()
+                    .find(|entry| entry.key == key)
+                    .map_or(0, |entry| entry.best_move)
+            },
+            Move::to_bits,
+        );
+
+        let entry = TtEntry {
+            key,
+            best_move,
+            score: score as i16,
+            depth: depth.min(u8::MAX as u32) as u8,
+            bound,
+            generation,
+        };
+
+        let current = &bucket.depth_preferred;
+        if current.key == key || current.generation != generation || entry.depth >= current.depth {
+            bucket.depth_preferred = entry;
+        } else {
+            bucket.always_replace = entry;
+        }
+    }
+
+    /// Occupancy of the table by the current search, in permill,
+    /// estimated from its first buckets.
+    pub fn hashfull(&self) -> usize {
+        let sample = &self.buckets[..HASHFULL_SAMPLE_SIZE.min(self.buckets.len())];
+
+        let used = sample
+            .iter()
+            .flat_map(|bucket| [bucket.depth_preferred, bucket.always_replace])
+            .filter(|entry| entry.depth > 0 && entry.generation == self.generation)
+            .count();
+
+        used * 1000 / (sample.len() * 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Piece;
+    use crate::search::MATE_SCORE;
+    use crate::squares::*;
+
+    /// Keys landing in the same bucket as `key`.
+    fn colliding_key(tt: &TranspositionTable, key: u64, n: u64) -> u64 {
+        key + n * tt.buckets.len() as u64
+    }
+
+    #[test]
+    fn mate_scores_are_stored_relative_to_the_node() {
+        let mut tt = TranspositionTable::new(1);
+        let mv = Move::encode(Piece::Queen, d1, h5);
+
+        // Mate in 2 plies from a node at ply 3, so 5 plies from the root.
+        tt.store(1, 4, MATE_SCORE - 5, Bound::Exact, Some(mv), 3);
+        tt.store(2, 4, -MATE_SCORE + 5, Bound::Lower, None, 3);
+        tt.store(3, 4, 120, Bound::Upper, None, 3);
+
+        let entry = tt.probe(1).unwrap();
+        assert_eq!(entry.score(3), MATE_SCORE - 5);
+        // Reached closer to the root, the same mate is closer too.
+        assert_eq!(entry.score(1), MATE_SCORE - 3);
+        assert_eq!(entry.best_move(), Some(mv));
+        assert_eq!(entry.depth(), 4);
+        assert_eq!(entry.bound(), Bound::Exact);
+
+        let entry = tt.probe(2).unwrap();
+        assert_eq!(entry.score(3), -MATE_SCORE + 5);
+        assert_eq!(entry.score(7), -MATE_SCORE + 9);
+        assert_eq!(entry.best_move(), None);
+
+        assert_eq!(tt.probe(3).unwrap().score(10), 120);
+        assert!(tt.probe(4).is_none());
+    }
+
+    #[test]
+    fn deep_entries_are_only_replaced_by_deeper_or_newer_ones() {
+        let mut tt = TranspositionTable::new(1);
+        let [a, b, c, d, e] = [0, 1, 2, 3, 4].map(|n| colliding_key(&tt, 5, n));
+        tt.new_search();
+
+        tt.store(a, 5, 0, Bound::Exact, None, 0);
+        // Shallower: goes to the always-replace slot.
+        tt.store(b, 3, 0, Bound::Exact, None, 0);
+        assert!(tt.probe(a).is_some() && tt.probe(b).is_some());
+
+        tt.store(c, 2, 0, Bound::Exact, None, 0);
+        assert!(tt.probe(a).is_some() && tt.probe(c).is_some());
+        assert!(tt.probe(b).is_none());
+
+        // Deeper: takes the depth-preferred slot.
+        tt.store(d, 6, 0, Bound::Exact, None, 0);
+        assert!(tt.probe(d).is_some() && tt.probe(c).is_some());
+        assert!(tt.probe(a).is_none());
+
+        // Entries of a previous search get replaced whatever their depth.
+        tt.new_search();
+        tt.store(e, 1, 0, Bound::Exact, None, 0);
+        assert!(tt.probe(e).is_some() && tt.probe(c).is_some());
+        assert!(tt.probe(d).is_none());
+    }
+
+    #[test]
+    fn clear_and_resize_drop_the_entries() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(1, 4, 0, Bound::Exact, None, 0);
+        tt.clear();
+        assert!(tt.probe(1).is_none());
+
+        let bucket_count = tt.buckets.len();
+        tt.store(1, 4, 0, Bound::Exact, None, 0);
+        tt.resize(2);
+        assert!(tt.probe(1).is_none());
+        assert_eq!(tt.buckets.len(), bucket_count * 2);
+        assert!(tt.buckets.len().is_power_of_two());
+    }
+
+    #[test]
+    fn hashfull_counts_the_current_search_entries() {
+        let mut tt = TranspositionTable::new(1);
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+
+        for key in 0..tt.buckets.len() as u64 {
+            tt.store(key, 2, 0, Bound::Exact, None, 0);
+            tt.store(colliding_key(&tt, key, 1), 1, 0, Bound::Exact, None, 0);
+        }
+        assert_eq!(tt.hashfull(), 1000);
+
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+    }
+}
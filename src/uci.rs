@@ -0,0 +1,479 @@
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::board::*;
+use crate::fens;
+use crate::move_generator::*;
+use crate::search::*;
+use crate::tt::*;
+
+/// Time kept in reserve for the communication with the GUI, in milliseconds.
+const MOVE_OVERHEAD: u64 = 50;
+
+/// When the GUI does not tell how many moves remain until the next time control,
+/// we assume the game will last this many more moves.
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+/// Parameters of the `go` command.
+#[derive(Debug, Default, Clone)]
+pub struct GoParameters {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u64>,
+    pub infinite: bool,
+}
+
+impl GoParameters {
+    fn parse<'t>(tokens: impl Iterator<Item = &'t str>) -> Self {
+        let mut parameters = Self::default();
+        let mut tokens = tokens.peekable();
+
+        while let Some(token) = tokens.next() {
+            // All the parameters but "infinite" are followed by a value
+            let mut value = || tokens.next().and_then(|v| v.parse::<i64>().ok());
+
+            match token {
+                "depth" => parameters.depth = value().map(|v| v.max(1) as u32),
+                "nodes" => parameters.nodes = value().map(|v| v.max(1) as u64),
+                "movetime" => parameters.movetime = value().map(|v| v.max(0) as u64),
+                // Some GUIs send negative times when the clock is running out
+                "wtime" => parameters.wtime = value().map(|v| v.max(0) as u64),
+                "btime" => parameters.btime = value().map(|v| v.max(0) as u64),
+                "winc" => parameters.winc = value().map(|v| v.max(0) as u64),
+                "binc" => parameters.binc = value().map(|v| v.max(0) as u64),
+                "movestogo" => parameters.movestogo = value().map(|v| v.max(1) as u64),
+                "infinite" => parameters.infinite = true,
+                _ => {}
+            }
+        }
+
+        parameters
+    }
+
+    /// Time we allow ourselves to think about the current move.
+    /// None means we only stop when told to, or when the depth or nodes limit is reached.
+    fn allocated_time(&self, side: Side) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+
+        if let Some(movetime) = self.movetime {
+            return Some(Duration::from_millis(
+                movetime.saturating_sub(MOVE_OVERHEAD).max(1),
+            ));
+        }
+
+        let (time, increment) = match side {
+            Side::White => (self.wtime?, self.winc.unwrap_or(0)),
+            Side::Black => (self.btime?, self.binc.unwrap_or(0)),
+            Side::Both => unreachable!(),
+        };
+
+        let moves_to_go = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO);
+        let remaining = time.saturating_sub(MOVE_OVERHEAD);
+        let allocated = (remaining / moves_to_go + increment * 3 / 4).min(remaining);
+
+        Some(Duration::from_millis(allocated.max(1)))
+    }
+}
+
+/// State of the search running in the background.
+struct SearchThread {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+pub struct Uci {
+    /// Current position, along with the moves of the game leading to it.
+    position: Position,
+    search: Option<SearchThread>,
+
+    /// Castling moves are written as king takes rook when UCI_Chess960 is set,
+    /// otherwise as the board needs (see `Board::castling_notation`).
+    castling_notation: CastlingNotation,
+
+    /// Shared with the search thread, which holds the lock while it runs.
+    tt: Arc<Mutex<TranspositionTable>>,
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uci {
+    pub fn new() -> Self {
+        Self {
+            position: Position::new(Board::from_fen(fens::STARTING_BOARD_FEN).unwrap()),
+            search: None,
+            castling_notation: CastlingNotation::KingToDestination,
+            tt: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_HASH_SIZE_MB))),
+        }
+    }
+
+    /// Reads commands from stdin until "quit" is received, or stdin is closed.
+    pub fn run(&mut self) {
+        let stdin = std::io::stdin();
+
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            if !self.handle_command(&line) {
+                break;
+            }
+        }
+
+        self.stop_search();
+    }
+
+    /// Returns false when the engine should quit.
+    fn handle_command(&mut self, line: &str) -> bool {
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("uci") => {
+                println!(
+                    "id name {} {}",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                );
+                println!("id author cmourglia");
+                println!(
+                    "option name Hash type spin default {} min {} max {}",
+                    DEFAULT_HASH_SIZE_MB, MIN_HASH_SIZE_MB, MAX_HASH_SIZE_MB
+                );
+                println!("option name UCI_Chess960 type check default false");
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                self.stop_search();
+                self.position = Position::new(Board::from_fen(fens::STARTING_BOARD_FEN).unwrap());
+                self.tt.lock().unwrap().clear();
+            }
+            Some("position") => {
+                self.stop_search();
+                match self.parse_position(tokens) {
+                    Ok(position) => self.position = position,
+                    Err(error) => println!("info string {}", error),
+                }
+            }
+            Some("go") => {
+                self.stop_search();
+                self.start_search(GoParameters::parse(tokens));
+            }
+            Some("stop") => self.stop_search(),
+            Some("setoption") => self.set_option(tokens),
+            Some("quit") => return false,
+            // Unknown commands are ignored, as the protocol requires.
+            _ => {}
+        }
+
+        true
+    }
+
+    /// position [fen <fen> | startpos] [moves <move1> ... <movei>]
+    fn parse_position<'t>(
+        &self,
+        mut tokens: impl Iterator<Item = &'t str>,
+    ) -> Result<Position, String> {
+        let board = match tokens.next() {
+            Some("startpos") => Board::from_fen(fens::STARTING_BOARD_FEN).unwrap(),
+            Some("fen") => {
+                let fen: Vec<&str> = tokens.by_ref().take_while(|t| *t != "moves").collect();
+                let fen = fen.join(" ");
+                Board::from_fen(&fen)
+                    .map_err(|error| format!("invalid fen '{}': {}", fen, error))?
+            }
+            _ => return Err("expected 'startpos' or 'fen'".to_string()),
+        };
+
+        // With a FEN, "moves" has already been consumed by take_while
+        let mut position = Position::new(board);
+        for token in tokens.skip_while(|t| *t == "moves") {
+            let board = position.board();
+            let mv = board
+                .parse_uci_move_with(token, self.castling_notation_for(board))
+                .map_err(|error| format!("{} in position command", error))?;
+            position.make_move(mv);
+        }
+
+        Ok(position)
+    }
+
+    /// setoption name <id> [value <x>]
+    fn set_option<'t>(&mut self, tokens: impl Iterator<Item = &'t str>) {
+        let (name, value) = parse_option(tokens);
+
+        // Option names are case insensitive.
+        match name.to_lowercase().as_str() {
+            "hash" => match value.and_then(|value| value.parse::<usize>().ok()) {
+                Some(size_mb) => {
+                    // The table cannot be touched while a search is using it.
+                    self.stop_search();
+                    self.tt.lock().unwrap().resize(size_mb);
+                }
+                None => println!("info string invalid value for option 'Hash'"),
+            },
+            "uci_chess960" => match value.as_deref() {
+                Some("true") => self.castling_notation = CastlingNotation::KingTakesRook,
+                Some("false") => self.castling_notation = CastlingNotation::KingToDestination,
+                _ => println!("info string invalid value for option 'UCI_Chess960'"),
+            },
+            _ => println!("info string unknown option '{}'", name),
+        }
+    }
+
+    fn start_search(&mut self, parameters: GoParameters) {
+        let stop = Arc::new(AtomicBool::new(false));
+        let position = self.position.clone();
+        let castling_notation = self.castling_notation_for(position.board());
+
+        let thread_stop = stop.clone();
+        let tt = self.tt.clone();
+        let handle = std::thread::spawn(move || {
+            let mut tt = tt.lock().unwrap();
+            search(
+                &position,
+                parameters,
+                castling_notation,
+                &thread_stop,
+                &mut tt,
+            );
+        });
+
+        self.search = Some(SearchThread { stop, handle });
+    }
+
+    fn castling_notation_for(&self, board: &Board) -> CastlingNotation {
+        match self.castling_notation {
+            CastlingNotation::KingTakesRook => CastlingNotation::KingTakesRook,
+            CastlingNotation::KingToDestination => board.castling_notation(),
+        }
+    }
+
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop.store(true, Ordering::Relaxed);
+            let _ = search.handle.join();
+        }
+    }
+}
+
+/// Background search, reporting its progress with "info" lines and its
+/// result with "bestmove".
+fn search(
+    position: &Position,
+    parameters: GoParameters,
+    castling_notation: CastlingNotation,
+    stop: &AtomicBool,
+    tt: &mut TranspositionTable,
+) {
+    let board = position.board();
+    let limits = SearchLimits {
+        depth: parameters.depth,
+        nodes: parameters.nodes,
+        time: parameters.allocated_time(board.side_to_move),
+    };
+
+    let mut search = Search::new(limits, stop, tt);
+    let result = search.run(position, |iteration| {
+        let pv: Vec<String> = iteration
+            .pv
+            .iter()
+            .map(|mv| mv.to_uci_with(castling_notation))
+            .collect();
+        let time = iteration.time.as_millis().max(1);
+
+        println!(
+            "info depth {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+            iteration.depth,
+            format_score(iteration.score),
+            iteration.nodes,
+            iteration.nodes as u128 * 1000 / time,
+            iteration.hashfull,
+            time,
+            pv.join(" ")
+        );
+    });
+
+    // In infinite mode, the result must not be sent before the GUI asks for it.
+    if parameters.infinite {
+        while !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // If the search got interrupted before completing its first iteration,
+    // any legal move is better than nothing.
+    let best_move = result
+        .map(|result| result.best_move)
+        .or_else(|| generate_moves(board).moves().first().copied());
+
+    match best_move {
+        Some(mv) => println!("bestmove {}", mv.to_uci_with(castling_notation)),
+        // No legal move (mate or stalemate), "0000" is the null move.
+        None => println!("bestmove 0000"),
+    }
+}
+
+/// Name and value of a setoption command, both may span several tokens.
+fn parse_option<'t>(tokens: impl Iterator<Item = &'t str>) -> (String, Option<String>) {
+    let tokens: Vec<&str> = tokens.collect();
+
+    let value_index = tokens.iter().position(|t| *t == "value");
+    let name = tokens[..value_index.unwrap_or(tokens.len())]
+        .iter()
+        .skip_while(|t| **t == "name")
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let value = value_index.map(|index| tokens[index + 1..].join(" "));
+
+    (name, value)
+}
+
+/// Scores are sent in centipawns, or in moves (not plies) until mate.
+fn format_score(score: i32) -> String {
+    if score >= MATE_THRESHOLD {
+        format!("mate {}", (MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_THRESHOLD {
+        format!("mate -{}", (MATE_SCORE + score) / 2)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn go(command: &str) -> GoParameters {
+        GoParameters::parse(command.split_whitespace())
+    }
+
+    #[test]
+    fn go_parameters_are_parsed() {
+        let parameters = go("wtime 60000 btime -250 winc 1000 binc 0 movestogo 0 depth 7");
+        assert_eq!(parameters.wtime, Some(60000));
+        // Negative times happen when the clock is running out.
+        assert_eq!(parameters.btime, Some(0));
+        assert_eq!(parameters.winc, Some(1000));
+        assert_eq!(parameters.binc, Some(0));
+        assert_eq!(parameters.movestogo, Some(1));
+        assert_eq!(parameters.depth, Some(7));
+        assert!(!parameters.infinite);
+
+        let parameters = go("infinite nodes 5000 movetime abc");
+        assert!(parameters.infinite);
+        assert_eq!(parameters.nodes, Some(5000));
+        assert_eq!(parameters.movetime, None);
+    }
+
+    #[test]
+    fn time_is_allocated_from_the_clock() {
+        let millis = |parameters: GoParameters, side| {
+            parameters
+                .allocated_time(side)
+                .map(|time| time.as_millis() as u64)
+        };
+
+        assert_eq!(
+            millis(go("movetime 1000"), Side::White),
+            Some(1000 - MOVE_OVERHEAD)
+        );
+        assert_eq!(millis(go("movetime 10"), Side::White), Some(1));
+
+        // A share of the remaining time, plus most of the increment.
+        let clock = "wtime 30050 winc 400 btime 10050 binc 0";
+        assert_eq!(millis(go(clock), Side::White), Some(30000 / 30 + 300));
+        assert_eq!(millis(go(clock), Side::Black), Some(10000 / 30));
+        let clock = "wtime 10050 winc 400 movestogo 4";
+        assert_eq!(millis(go(clock), Side::White), Some(10000 / 4 + 300));
+        // Never more than what is left on the clock.
+        let clock = "wtime 150 winc 5000";
+        assert_eq!(millis(go(clock), Side::White), Some(100));
+
+        assert_eq!(millis(go("depth 5"), Side::White), None);
+        assert_eq!(millis(go("infinite movetime 1000"), Side::White), None);
+    }
+
+    #[test]
+    fn mate_scores_are_sent_in_moves() {
+        assert_eq!(format_score(MATE_SCORE - 1), "mate 1");
+        assert_eq!(format_score(MATE_SCORE - 3), "mate 2");
+        assert_eq!(format_score(-MATE_SCORE + 2), "mate -1");
+        assert_eq!(format_score(-MATE_SCORE + 4), "mate -2");
+        assert_eq!(format_score(-35), "cp -35");
+    }
+
+    #[test]
+    fn positions_are_set_up_with_their_moves() {
+        let mut uci = Uci::new();
+
+        uci.handle_command("position startpos moves e2e4 e7e5 g1f3");
+        assert_eq!(
+            uci.position.board().to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+        assert_eq!(uci.position.previous_hashes().count(), 3);
+
+        // The FEN ends at "moves".
+        uci.handle_command("position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1 e8d7");
+        assert_eq!(
+            uci.position.board().to_fen(),
+            "8/3k4/8/8/8/8/8/5RK1 w - - 2 2"
+        );
+
+        // An illegal move rejects the whole command.
+        let fen = uci.position.board().to_fen();
+        uci.handle_command("position startpos moves e2e4 e2e4");
+        assert_eq!(uci.position.board().to_fen(), fen);
+        assert!(uci
+            .parse_position("startpos moves e2e4 e2e4".split_whitespace())
+            .is_err());
+        assert!(uci.parse_position("fen 8/8 w".split_whitespace()).is_err());
+    }
+
+    #[test]
+    fn options_are_set() {
+        let mut uci = Uci::new();
+
+        let size_mb = TranspositionTable::new(4).size_mb();
+        assert_ne!(uci.tt.lock().unwrap().size_mb(), size_mb);
+        uci.handle_command("setoption name Hash value 4");
+        assert_eq!(uci.tt.lock().unwrap().size_mb(), size_mb);
+        uci.handle_command("setoption name hash value lots");
+        assert_eq!(uci.tt.lock().unwrap().size_mb(), size_mb);
+
+        uci.handle_command("setoption name UCI_Chess960 value true");
+        assert_eq!(uci.castling_notation, CastlingNotation::KingTakesRook);
+        uci.handle_command("position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1h1");
+        assert_eq!(
+            uci.position.board().to_fen(),
+            "4k3/8/8/8/8/8/8/5RK1 b - - 1 1"
+        );
+        uci.handle_command("setoption name UCI_Chess960 value false");
+        assert_eq!(uci.castling_notation, CastlingNotation::KingToDestination);
+
+        let option = parse_option("name Clear Hash".split_whitespace());
+        assert_eq!(option, ("Clear Hash".to_string(), None));
+        let option = parse_option("name Move Overhead value 100".split_whitespace());
+        assert_eq!(
+            option,
+            ("Move Overhead".to_string(), Some("100".to_string()))
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use crate::rand::get_random_u64_number;
+
+/// Random keys used to compute a position hash.
+/// A position key is the XOR of the keys of each of its features (piece on a square,
+/// side to move, castling rights and en-passant file), which allows to update it
+/// incrementally when a move is played.
+pub struct ZobristKeys {
+    /// One key per piece type (indexed like `Board::pieces`) per square
+    pub pieces: [[u64; 64]; 12],
+
+    /// XORed in when black is to move
+    pub side: u64,
+
+    /// One key for each combination of castling rights
+    pub castling: [u64; 16],
+
+    /// One key per file, XORed in when an en-passant square is available
+    pub en_passant_file: [u64; 8],
+}
+
+pub static ZOBRIST_KEYS: ZobristKeys = ZobristKeys::new();
+
+impl ZobristKeys {
+    const fn new() -> Self {
+        let mut random_state = 1804289383;
+
+        let mut pieces = [[0u64; 64]; 12];
+        let mut piece = 0;
+        while piece < 12 {
+            let mut square = 0;
+            while square < 64 {
+                let (key, rnd) = get_random_u64_number(random_state);
+                pieces[piece][square] = key;
+                random_state = rnd;
+                square += 1;
+            }
+            piece += 1;
+        }
+
+        let (side, rnd) = get_random_u64_number(random_state);
+        random_state = rnd;
+
+        let mut castling = [0u64; 16];
+        let mut rights = 0;
+        while rights < 16 {
+            let (key, rnd) = get_random_u64_number(random_state);
+            castling[rights] = key;
+            random_state = rnd;
+            rights += 1;
+        }
+
+        let mut en_passant_file = [0u64; 8];
+        let mut file = 0;
+        while file < 8 {
+            let (key, rnd) = get_random_u64_number(random_state);
+            en_passant_file[file] = key;
+            random_state = rnd;
+            file += 1;
+        }
+
+        Self {
+            pieces,
+            side,
+            castling,
+            en_passant_file,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::Board;
+    use crate::fens;
+    use crate::move_generator::*;
+
+    fn play_uci_moves(board: &mut Board, moves: &[&str]) {
+        for uci in moves {
+            let mv = *generate_moves(board)
+                .moves()
+                .iter()
+                .find(|mv| Move::to_uci(**mv) == *uci)
+                .unwrap();
+            board.play_move(mv);
+            assert_eq!(board.hash, board.compute_hash());
+        }
+    }
+
+    #[test]
+    fn transpositions_share_the_same_key() {
+        let start = Board::from_fen(fens::STARTING_BOARD_FEN).unwrap();
+
+        let mut board = start;
+        play_uci_moves(&mut board, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(board.hash, start.hash);
+
+        let mut board_a = start;
+        play_uci_moves(&mut board_a, &["e2e3", "e7e6", "d2d3", "d7d6"]);
+        let mut board_b = start;
+        play_uci_moves(&mut board_b, &["d2d3", "d7d6", "e2e3", "e7e6"]);
+        assert_eq!(board_a.hash, board_b.hash);
+
+        // Same pieces, but different castling rights
+        let mut board_c = start;
+        play_uci_moves(
+            &mut board_c,
+            &["e2e3", "e7e6", "e1e2", "e8e7", "e2e1", "e7e8"],
+        );
+        assert_ne!(board_c.hash, start.hash);
+    }
+}